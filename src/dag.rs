@@ -0,0 +1,375 @@
+use std::{collections::HashMap, hash::Hash};
+
+// Directed graph over named nodes, it may contain cycles
+// The names are interned into the indices 0..n, all queries work on these indices
+#[derive(Debug, Clone)]
+pub struct Graph<K> {
+    names: Vec<K>,
+    index: HashMap<K, usize>,
+    edges: Vec<Vec<usize>>,
+}
+
+// A loop of nodes, each pointing to the next and the last one back to the first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cycle<K> {
+    pub nodes: Vec<K>,
+}
+
+impl<K: Eq + Hash + Clone> Graph<K> {
+    // Build the graph from every node and the nodes it points to
+    // Nodes only appearing as a target are added without outgoing edges
+    pub fn new(adjacency: impl IntoIterator<Item = (K, Vec<K>)>) -> Self {
+        let mut graph = Self {
+            names: Vec::new(),
+            index: HashMap::new(),
+            edges: Vec::new(),
+        };
+        for (from, to) in adjacency {
+            let from = graph.intern(from);
+            for t in to {
+                let t = graph.intern(t);
+                graph.edges[from].push(t);
+            }
+        }
+        graph
+    }
+
+    fn intern(&mut self, name: K) -> usize {
+        if let Some(i) = self.index.get(&name) {
+            return *i;
+        }
+        self.names.push(name.clone());
+        self.edges.push(Vec::new());
+        self.index.insert(name, self.edges.len() - 1);
+        self.edges.len() - 1
+    }
+
+    // Number of nodes
+    fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    // Index of the node with the given name
+    pub fn index(&self, name: &K) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    // The nodes reachable from the given node following the edges forward or backward
+    fn reachable(&self, from: usize, backward: bool) -> Vec<bool> {
+        let mut edges = vec![Vec::new(); self.len()];
+        if backward {
+            for (f, to) in self.edges.iter().enumerate() {
+                to.iter().for_each(|t| edges[*t].push(f));
+            }
+        }
+        let edges = if backward { &edges } else { &self.edges };
+        let mut seen = vec![false; self.len()];
+        seen[from] = true;
+        let mut stack = vec![from];
+        while let Some(n) = stack.pop() {
+            for t in &edges[n] {
+                if !seen[*t] {
+                    seen[*t] = true;
+                    stack.push(*t);
+                }
+            }
+        }
+        seen
+    }
+
+    // Kahn's algorithm on the kept nodes,
+    // repeatedly take a node without remaining incoming edges
+    // Nodes on a cycle never lose all their incoming edges and are left out
+    fn topological_order(&self, keep: &[bool]) -> Vec<usize> {
+        let mut incoming = vec![0; self.len()];
+        for (from, to) in self.edges.iter().enumerate() {
+            for t in to.iter().filter(|t| keep[from] && keep[**t]) {
+                incoming[*t] += 1;
+            }
+        }
+        let mut order = (0..self.len())
+            .filter(|n| keep[*n] && incoming[*n] == 0)
+            .collect::<Vec<_>>();
+        let mut i = 0;
+        while i < order.len() {
+            for t in self.edges[order[i]].iter().filter(|t| keep[**t]) {
+                incoming[*t] -= 1;
+                if incoming[*t] == 0 {
+                    order.push(*t);
+                }
+            }
+            i += 1;
+        }
+        order
+    }
+
+    // Find a cycle among the kept nodes missing from the topological order
+    // Every one of them has a predecessor that is missing as well,
+    // so walking backwards over these predecessors must run into a loop
+    fn find_cycle(&self, keep: &[bool], order: &[usize]) -> Cycle<K> {
+        let mut missing = keep.to_vec();
+        order.iter().for_each(|n| missing[*n] = false);
+        let mut predecessor = vec![usize::MAX; self.len()];
+        for (from, to) in self.edges.iter().enumerate() {
+            for t in to {
+                if missing[from] && missing[*t] {
+                    predecessor[*t] = from;
+                }
+            }
+        }
+
+        // position of every visited node on the backwards walk
+        let mut seen = vec![usize::MAX; self.len()];
+        let mut walk = Vec::new();
+        let mut n = (0..self.len()).find(|n| missing[*n]).unwrap();
+        while seen[n] == usize::MAX {
+            seen[n] = walk.len();
+            walk.push(n);
+            n = predecessor[n];
+        }
+        let mut nodes = walk[seen[n]..]
+            .iter()
+            .map(|n| self.names[*n].clone())
+            .collect::<Vec<_>>();
+        nodes.reverse();
+        Cycle { nodes }
+    }
+
+    // Compute the topological order of the nodes on the paths between two nodes,
+    // the nodes reachable from the first one which can reach the second one
+    // Fails with one of the loops when a cycle lies on these paths,
+    // cycles elsewhere in the graph don't affect the paths and are ignored
+    pub fn dag_between(&self, from: usize, to: usize) -> Result<Dag<'_, K>, Cycle<K>> {
+        let keep = self
+            .reachable(from, false)
+            .into_iter()
+            .zip(self.reachable(to, true))
+            .map(|(f, t)| f && t)
+            .collect::<Vec<_>>();
+        let order = self.topological_order(&keep);
+        if order.len() < keep.iter().filter(|k| **k).count() {
+            return Err(self.find_cycle(&keep, &order));
+        }
+        let mut rank = vec![usize::MAX; self.len()];
+        for (i, n) in order.iter().enumerate() {
+            rank[*n] = i;
+        }
+        Ok(Dag {
+            graph: self,
+            order,
+            rank,
+        })
+    }
+
+    // Number of simple paths between two nodes that pass all the via nodes
+    // A simple path visits every node at most once, so this also works on cycles
+    // Depth first search over every path, only feasible for small graphs
+    pub fn simple_paths(&self, from: usize, to: usize, via: &[usize]) -> u64 {
+        struct Search<'a, K> {
+            graph: &'a Graph<K>,
+            to: usize,
+            via: &'a [usize],
+            on_path: Vec<bool>,
+        }
+
+        impl<K> Search<'_, K> {
+            fn run(&mut self, n: usize) -> u64 {
+                if n == self.to {
+                    return self.via.iter().all(|v| self.on_path[*v] || *v == n) as u64;
+                }
+                self.on_path[n] = true;
+                let mut paths = 0;
+                for t in &self.graph.edges[n] {
+                    if !self.on_path[*t] {
+                        paths += self.run(*t);
+                    }
+                }
+                self.on_path[n] = false;
+                paths
+            }
+        }
+
+        Search {
+            graph: self,
+            to,
+            via,
+            on_path: vec![false; self.len()],
+        }
+        .run(from)
+    }
+}
+
+// View of the paths between two nodes of a graph, which contain no cycles
+// The topological order is computed once when the nodes are checked for cycles
+// Nodes off these paths are left out, no path between two nodes of the view leaves it
+#[derive(Debug, Clone)]
+pub struct Dag<'a, K> {
+    graph: &'a Graph<K>,
+    // every edge points from an earlier to a later node of the order
+    order: Vec<usize>,
+    // position of every node in the order, usize::MAX for nodes left out
+    rank: Vec<usize>,
+}
+
+impl<K: Eq + Hash + Clone> Dag<'_, K> {
+    // Number of paths from the source to every node, indexed by node
+    // Only nodes behind the source in the topological order can be reached,
+    // so the counts are pushed forward along the order starting at the source
+    // Nodes left out of the view count no paths
+    pub fn paths_from(&self, source: usize) -> Vec<u64> {
+        let mut paths = vec![0; self.graph.len()];
+        paths[source] = 1;
+        let start = self.rank[source].min(self.order.len());
+        for n in &self.order[start..] {
+            if paths[*n] == 0 {
+                continue;
+            }
+            for t in self.graph.edges[*n]
+                .iter()
+                .filter(|t| self.rank[**t] != usize::MAX)
+            {
+                paths[*t] += paths[*n];
+            }
+        }
+        paths
+    }
+
+    // Number of paths between two nodes
+    // Only the part of the order between both nodes is visited
+    pub fn paths(&self, from: usize, to: usize) -> u64 {
+        if from == to {
+            return 1;
+        }
+        let left_out = |n: usize| self.rank[n] == usize::MAX;
+        if left_out(from) || left_out(to) || self.rank[from] > self.rank[to] {
+            return 0;
+        }
+        let mut paths = vec![0; self.graph.len()];
+        paths[from] = 1;
+        for n in &self.order[self.rank[from]..self.rank[to]] {
+            if paths[*n] == 0 {
+                continue;
+            }
+            for t in &self.graph.edges[*n] {
+                paths[*t] += paths[*n];
+            }
+        }
+        paths[to]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(adjacency: &[(&'static str, &[&'static str])]) -> Graph<&'static str> {
+        Graph::new(adjacency.iter().map(|(from, to)| (*from, to.to_vec())))
+    }
+
+    // two diamonds in a row, a dead end and a node behind the target
+    fn diamonds() -> Graph<&'static str> {
+        graph(&[
+            ("a", &["b", "c"]),
+            ("b", &["d"]),
+            ("c", &["d", "x"]),
+            ("d", &["e", "f"]),
+            ("e", &["g"]),
+            ("f", &["g"]),
+            ("g", &["h"]),
+        ])
+    }
+
+    #[test]
+    fn path_counts() {
+        let graph = diamonds();
+        let i = |name| graph.index(&name).unwrap();
+        let dag = graph.dag_between(i("a"), i("h")).unwrap();
+        assert_eq!(dag.paths(i("a"), i("g")), 4);
+        assert_eq!(dag.paths(i("c"), i("g")), 2);
+        assert_eq!(dag.paths(i("a"), i("a")), 1);
+        assert_eq!(dag.paths(i("g"), i("a")), 0);
+        assert_eq!(dag.paths(i("a"), i("h")), 4);
+        // the dead end is not between a and h
+        assert_eq!(dag.paths(i("c"), i("x")), 0);
+        assert_eq!(dag.paths(i("x"), i("h")), 0);
+    }
+
+    #[test]
+    fn paths_from_a_source() {
+        let graph = diamonds();
+        let i = |name| graph.index(&name).unwrap();
+        let dag = graph.dag_between(i("a"), i("h")).unwrap();
+        let from_a = dag.paths_from(i("a"));
+        let counts = ["a", "b", "c", "d", "x", "e", "f", "g", "h"].map(|name| from_a[i(name)]);
+        assert_eq!(counts, [1, 1, 1, 2, 0, 2, 2, 4, 4]);
+        // every pair agrees with the single queries
+        for from in 0..graph.len() {
+            let counts = dag.paths_from(from);
+            for (to, count) in counts.iter().enumerate() {
+                assert_eq!(*count, dag.paths(from, to));
+            }
+        }
+    }
+
+    #[test]
+    fn cycles() {
+        let graph = graph(&[
+            ("a", &["b"]),
+            ("b", &["c", "out"]),
+            ("c", &["d"]),
+            ("d", &["b"]),
+        ]);
+        let i = |name| graph.index(&name).unwrap();
+        assert_eq!(
+            graph.dag_between(i("a"), i("out")).unwrap_err(),
+            Cycle {
+                nodes: vec!["c", "d", "b"]
+            }
+        );
+        assert_eq!(graph.simple_paths(i("a"), i("out"), &[]), 1);
+        assert_eq!(graph.simple_paths(i("a"), i("out"), &[i("c")]), 0);
+        assert_eq!(graph.simple_paths(i("c"), i("out"), &[]), 1);
+    }
+
+    #[test]
+    fn simple_paths_match_on_a_dag() {
+        let graph = diamonds();
+        let i = |name| graph.index(&name).unwrap();
+        let dag = graph.dag_between(i("a"), i("h")).unwrap();
+        assert_eq!(
+            graph.simple_paths(i("a"), i("g"), &[]),
+            dag.paths(i("a"), i("g"))
+        );
+        assert_eq!(
+            graph.simple_paths(i("a"), i("h"), &[i("c"), i("e")]),
+            dag.paths(i("a"), i("c")) * dag.paths(i("c"), i("e")) * dag.paths(i("e"), i("h"))
+        );
+    }
+
+    #[test]
+    fn cycles_off_the_paths() {
+        // c and d loop behind b without reaching out,
+        // e and f loop into out but can't be reached from a
+        let graph = graph(&[
+            ("a", &["b"]),
+            ("b", &["c", "out"]),
+            ("c", &["d"]),
+            ("d", &["c"]),
+            ("e", &["f"]),
+            ("f", &["e", "out"]),
+        ]);
+        let i = |name| graph.index(&name).unwrap();
+        let dag = graph.dag_between(i("a"), i("out")).unwrap();
+        assert_eq!(dag.paths(i("a"), i("out")), 1);
+        assert_eq!(dag.paths_from(i("a"))[i("out")], 1);
+        assert_eq!(dag.paths(i("c"), i("d")), 0);
+        // the loops are on the paths of other ends
+        assert_eq!(
+            graph.dag_between(i("a"), i("d")).unwrap_err(),
+            Cycle {
+                nodes: vec!["d", "c"]
+            }
+        );
+        assert!(graph.dag_between(i("e"), i("out")).is_err());
+    }
+}
@@ -1,7 +1,8 @@
-use good_lp::*;
-use regex::Regex;
+use std::{collections::VecDeque, fmt::Display};
 
-use crate::utils::get_input;
+use itertools::Itertools;
+
+use crate::utils::{get_input, has_arg};
 
 #[allow(dead_code)]
 const TEST_DATA: &str = r#"
@@ -10,113 +11,1010 @@ const TEST_DATA: &str = r#"
 [.###.#] (0,1,2,3,4) (0,3,4) (0,1,2,4,5) (1,2) {10,11,11,5,10,5}
 "#;
 
-fn press(lights: &[bool], button: &Vec<bool>) -> Vec<bool> {
-    lights.iter().zip(button).map(|(a, b)| *a ^ *b).collect()
-}
-
 type Data = (Vec<bool>, Vec<Vec<bool>>, Vec<u32>);
 
 fn get_data() -> Vec<Data> {
     let data_str = get_input(10).unwrap();
     // let data_str = TEST_DATA;
 
-    let machine_re = Regex::new(r"\[(.*)\] (.*) \{(.*)\}").unwrap();
-    let button_re = Regex::new(r"\(([\d,]+)\)").unwrap();
-
-    machine_re
-        .captures_iter(&data_str)
-        .map(|c| {
-            let s: [&str; 3] = c.extract().1;
-            let target: Vec<bool> = s[0].chars().map(|s| s == '#').collect();
-            let buttons_len = target.len();
-            let buttons = button_re
-                .captures_iter(s[1])
-                .map(|c| {
-                    let s: [&str; 1] = c.extract().1;
-                    let mut b = vec![false; buttons_len];
-                    s[0].split(",")
-                        .map(|i| i.parse::<usize>().unwrap())
-                        .for_each(|i| b[i] = true);
-                    b
-                })
-                .collect::<Vec<_>>();
-            let joltages = s[2].split(",").map(|i| i.parse().unwrap()).collect();
-            (target, buttons, joltages)
+    parse_machines(&data_str).unwrap_or_else(|e| panic!("Invalid machine on {e}"))
+}
+
+// Reasons for a line not describing a machine as `[lights] (buttons...) {joltages}`
+// Buttons are named by their position on the line
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ParseErrorKind {
+    MissingLights,
+    InvalidLight(char),
+    NoLights,
+    MissingJoltages,
+    UnexpectedToken(String),
+    InvalidNumber(String),
+    EmptyButton(usize),
+    ButtonOutOfRange(usize, usize, usize),
+    DuplicateIndex(usize, usize),
+    JoltageCount(usize, usize),
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingLights => write!(f, "the line has to start with a [light diagram]"),
+            Self::InvalidLight(c) => write!(f, "'{c}' is no light, only '.' and '#' are allowed"),
+            Self::NoLights => write!(f, "the light diagram is empty"),
+            Self::MissingJoltages => write!(f, "the line has to end with the {{joltages}}"),
+            Self::UnexpectedToken(t) => write!(f, "unexpected '{t}'"),
+            Self::InvalidNumber(n) => write!(f, "'{n}' is no number"),
+            Self::EmptyButton(b) => write!(f, "button {b} has no lights"),
+            Self::ButtonOutOfRange(b, i, n) => {
+                write!(
+                    f,
+                    "button {b} toggles light {i}, but there are only {n} lights"
+                )
+            }
+            Self::DuplicateIndex(b, i) => write!(f, "button {b} lists light {i} twice"),
+            Self::JoltageCount(n, j) => write!(f, "{j} joltages for {n} lights"),
+        }
+    }
+}
+
+// A parse error and the line it occurred on, counting from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ParseError {
+    line: usize,
+    kind: ParseErrorKind,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+// Comma separated numbers between the given brackets, None when the brackets don't match
+fn parse_list<T: std::str::FromStr>(
+    token: &str,
+    open: char,
+    close: char,
+) -> Option<Result<Vec<T>, ParseErrorKind>> {
+    let inner = token.strip_prefix(open)?.strip_suffix(close)?;
+    if inner.is_empty() {
+        return Some(Ok(Vec::new()));
+    }
+    Some(
+        inner
+            .split(',')
+            .map(|n| {
+                n.parse()
+                    .map_err(|_| ParseErrorKind::InvalidNumber(n.to_string()))
+            })
+            .collect(),
+    )
+}
+
+// Parse a single machine description
+// Button indices have to refer to a light of the diagram,
+// and there has to be one joltage per light
+fn parse_machine(line: &str) -> Result<Data, ParseErrorKind> {
+    let mut tokens = line.split_whitespace().collect::<Vec<_>>();
+
+    let lights = tokens
+        .first()
+        .and_then(|t| t.strip_prefix('[')?.strip_suffix(']'))
+        .ok_or(ParseErrorKind::MissingLights)?;
+    let target = lights
+        .chars()
+        .map(|c| match c {
+            '.' => Ok(false),
+            '#' => Ok(true),
+            c => Err(ParseErrorKind::InvalidLight(c)),
         })
-        .collect::<Vec<_>>()
+        .collect::<Result<Vec<_>, _>>()?;
+    if target.is_empty() {
+        return Err(ParseErrorKind::NoLights);
+    }
+
+    let joltages = match tokens.pop().filter(|_| !tokens.is_empty()) {
+        Some(t) => parse_list::<u32>(t, '{', '}').ok_or(ParseErrorKind::MissingJoltages)??,
+        None => return Err(ParseErrorKind::MissingJoltages),
+    };
+    if joltages.len() != target.len() {
+        return Err(ParseErrorKind::JoltageCount(target.len(), joltages.len()));
+    }
+
+    let buttons = tokens[1..]
+        .iter()
+        .enumerate()
+        .map(|(b, t)| {
+            let indices = parse_list::<usize>(t, '(', ')')
+                .ok_or_else(|| ParseErrorKind::UnexpectedToken(t.to_string()))??;
+            if indices.is_empty() {
+                return Err(ParseErrorKind::EmptyButton(b));
+            }
+            let mut button = vec![false; target.len()];
+            for i in indices {
+                match button.get_mut(i) {
+                    None => return Err(ParseErrorKind::ButtonOutOfRange(b, i, target.len())),
+                    Some(true) => return Err(ParseErrorKind::DuplicateIndex(b, i)),
+                    Some(l) => *l = true,
+                }
+            }
+            Ok(button)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((target, buttons, joltages))
+}
+
+// Parse one machine per line, skipping blank lines
+fn parse_machines(data: &str) -> Result<Vec<Data>, ParseError> {
+    data.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| parse_machine(l).map_err(|kind| ParseError { line: i + 1, kind }))
+        .collect()
+}
+
+// Button in the notation of the input, e.g. `(0,2)`
+fn format_button(button: &[bool]) -> String {
+    format!("({})", (0..button.len()).filter(|i| button[*i]).join(","))
+}
+
+// Machine description in the format read by parse_machine
+fn format_machine((target, buttons, joltages): &Data) -> String {
+    let lights = target
+        .iter()
+        .map(|l| if *l { '#' } else { '.' })
+        .collect::<String>();
+    let buttons = buttons.iter().map(|b| format_button(b)).join(" ");
+    format!("[{lights}] {buttons} {{{}}}", joltages.iter().join(","))
 }
 
+// Reasons for a machine having no press plan, or none that can be trusted
+// Lights and counters are named by their index in the machine description
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Unsolvable {
+    // the button equations of these lights contradict each other
+    Lights(Vec<usize>),
+    // too many free buttons and too many lights to search for the fewest presses
+    TooManyFreeButtons(usize),
+    // the button equations of these counters contradict each other
+    #[cfg(feature = "native-ilp")]
+    Counters(Vec<usize>),
+    // the equations hold, but only with negative or fractional presses
+    #[cfg(any(feature = "native-ilp", feature = "lp"))]
+    NoWholePlan,
+    // the press plan of the solver reaches these joltages instead
+    MissedJoltages(Vec<u64>),
+    // total presses of the native solver and the LP backend, None when unsolvable
+    #[cfg(all(feature = "native-ilp", feature = "lp"))]
+    SolversDisagree(Option<u64>, Option<u64>),
+}
+
+impl Display for Unsolvable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lights(l) if l.len() == 1 => write!(f, "no button toggles light {}", l[0]),
+            Self::Lights(l) => write!(
+                f,
+                "lights {} can't reach the pattern together",
+                l.iter().join(", ")
+            ),
+            Self::TooManyFreeButtons(k) => {
+                write!(f, "{k} free buttons and the lights are too many to search")
+            }
+            #[cfg(feature = "native-ilp")]
+            Self::Counters(c) if c.len() == 1 => write!(f, "no button increases counter {}", c[0]),
+            #[cfg(feature = "native-ilp")]
+            Self::Counters(c) => write!(
+                f,
+                "counters {} can't reach their joltages together",
+                c.iter().join(", ")
+            ),
+            #[cfg(any(feature = "native-ilp", feature = "lp"))]
+            Self::NoWholePlan => write!(f, "the joltages need negative or fractional presses"),
+            Self::MissedJoltages(reached) => {
+                write!(f, "the press plan reaches the joltages {reached:?}")
+            }
+            #[cfg(all(feature = "native-ilp", feature = "lp"))]
+            Self::SolversDisagree(native, lp) => {
+                let total =
+                    |t: &Option<u64>| t.map_or("no plan".to_string(), |t| format!("{t} presses"));
+                write!(
+                    f,
+                    "the native solver finds {}, the LP backend {}",
+                    total(native),
+                    total(lp)
+                )
+            }
+        }
+    }
+}
+
+// Indices of the set bits
+fn bit_indices(mask: u128) -> Vec<usize> {
+    (0..128).filter(|i| mask >> i & 1 == 1).collect()
+}
+
+// Largest number of free buttons or lights, whose combinations are searched one by one
+const MAX_SEARCH_BITS: usize = 20;
+
+// Breadth first search over all states of the lights, starting with all lights off
+// Every state remembers the button pressed to reach it first,
+// the buttons on the way back from the target are the fewest presses
+// The target must be reachable
+fn fewest_presses(target: &[bool], buttons: &[Vec<bool>]) -> Vec<usize> {
+    let mask = |lights: &[bool]| {
+        lights
+            .iter()
+            .enumerate()
+            .fold(0usize, |m, (i, l)| m | (*l as usize) << i)
+    };
+    let masks = buttons.iter().map(|b| mask(b)).collect::<Vec<_>>();
+    let goal = mask(target);
+
+    let mut pressed = vec![usize::MAX; 1 << target.len()];
+    let mut queue = VecDeque::from([0]);
+    while let Some(state) = queue.pop_front() {
+        if state == goal {
+            break;
+        }
+        for (b, m) in masks.iter().enumerate() {
+            let next = state ^ m;
+            if next != 0 && pressed[next] == usize::MAX {
+                pressed[next] = b;
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let mut presses = Vec::new();
+    let mut state = goal;
+    while state != 0 {
+        presses.push(pressed[state]);
+        state ^= masks[pressed[state]];
+    }
+    presses.sort();
+    presses
+}
+
+// Find the fewest button presses to reach the light pattern
+// Pressing a button twice cancels out, so every button is pressed at most once
+// and the problem is a linear system over GF(2): one equation per light, one variable per button.
+// Each row stores the buttons toggling a light as bits and the target state behind them.
+// After Gauss-Jordan elimination, every combination of the free buttons gives one solution,
+// try them all and keep the one with the fewest presses,
+// unless there are more free buttons than lights, then the light states are searched instead
+// Every row also remembers the lights it is the sum of,
+// so a contradicting row names exactly the lights in conflict
+// Returns the indices of the buttons to press
+fn solve_lights(target: &[bool], buttons: &[Vec<bool>]) -> Result<Vec<usize>, Unsolvable> {
+    let n = buttons.len();
+    assert!(n < 128, "Too many buttons");
+    assert!(target.len() <= 128, "Too many lights");
+    let mut sources = (0..target.len()).map(|i| 1u128 << i).collect::<Vec<_>>();
+    let mut rows = target
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            let row = (0..n)
+                .filter(|j| buttons[*j][i])
+                .fold(0u128, |r, j| r | 1 << j);
+            row | (*t as u128) << n
+        })
+        .collect::<Vec<_>>();
+
+    // reduce to row echelon form, remembering the pivot column of every row
+    let mut pivots = Vec::new();
+    for col in 0..n {
+        let r = pivots.len();
+        let Some(p) = (r..rows.len()).find(|i| rows[*i] >> col & 1 == 1) else {
+            continue;
+        };
+        rows.swap(r, p);
+        sources.swap(r, p);
+        for i in (0..rows.len()).filter(|i| *i != r) {
+            if rows[i] >> col & 1 == 1 {
+                rows[i] ^= rows[r];
+                sources[i] ^= sources[r];
+            }
+        }
+        pivots.push(col);
+    }
+
+    // a remaining row 0 = 1 has no solution
+    if let Some(r) = (pivots.len()..rows.len()).find(|r| rows[*r] != 0) {
+        return Err(Unsolvable::Lights(bit_indices(sources[r])));
+    }
+
+    // solution with all free buttons unpressed
+    let solution = pivots
+        .iter()
+        .enumerate()
+        .filter(|(r, _)| rows[*r] >> n & 1 == 1)
+        .fold(0u128, |x, (_, col)| x | 1 << col);
+
+    // pressing a free button requires toggling all pivot buttons depending on it
+    let null_space = (0..n)
+        .filter(|col| !pivots.contains(col))
+        .map(|col| {
+            pivots
+                .iter()
+                .enumerate()
+                .filter(|(r, _)| rows[*r] >> col & 1 == 1)
+                .fold(1u128 << col, |x, (_, p)| x | 1 << p)
+        })
+        .collect::<Vec<_>>();
+
+    // trying every combination of the free buttons takes 2^k steps,
+    // with more free buttons than lights searching the 2^lights light states is cheaper
+    if null_space.len().min(target.len()) > MAX_SEARCH_BITS {
+        return Err(Unsolvable::TooManyFreeButtons(null_space.len()));
+    }
+    if null_space.len() > target.len() {
+        return Ok(fewest_presses(target, buttons));
+    }
+
+    // walk all combinations in gray code order, changing a single free button each step
+    let mut x = solution;
+    let mut best = solution;
+    for i in 1..1u64 << null_space.len() {
+        x ^= null_space[i.trailing_zeros() as usize];
+        if x.count_ones() < best.count_ones() {
+            best = x;
+        }
+    }
+
+    Ok((0..n).filter(|j| best >> j & 1 == 1).collect())
+}
+
+// Unsolvable machines are reported and left out of the sum
 pub fn part_1() {
     let data = get_data();
 
-    let mut levels = Vec::new();
-    for (target, buttons, _) in data.iter() {
-        let mut lights = vec![vec![false; target.len()]];
-        let mut level = 0;
-        // Brute force iterate over all combinations increasing in number
-        // returning level when target is reached
-        'outer: loop {
-            let mut new_lights = Vec::new();
-            level += 1;
-            for l in lights.iter() {
-                for b in buttons {
-                    let light = press(l, b);
-                    if light == *target {
-                        // target reached, break out of loop
-                        break 'outer;
+    let mut sum = 0;
+    let mut unsolvable = 0;
+    for (m, (target, buttons, _)) in data.iter().enumerate() {
+        match solve_lights(target, buttons) {
+            Ok(presses) => sum += presses.len(),
+            Err(e) => {
+                println!("Machine {m} {}: skipped, {e}", format_machine(&data[m]));
+                unsolvable += 1;
+            }
+        }
+    }
+
+    if unsolvable == 0 {
+        assert_eq!(sum, 441);
+        println!("Part 1: {sum}");
+    } else {
+        println!("Part 1: {sum} ({unsolvable} machines skipped)");
+    }
+}
+
+// Divide the row by the greatest common divisor of its entries
+#[cfg(feature = "native-ilp")]
+fn reduce(row: &mut [i64]) {
+    let gcd = row.iter().fold(0, |a, b| {
+        let (mut a, mut b) = (a, b.abs());
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    });
+    if gcd > 1 {
+        row.iter_mut().for_each(|x| *x /= gcd);
+    }
+}
+
+// Integer division rounding down and up, for any signs
+#[cfg(feature = "native-ilp")]
+fn floor_div(a: i64, b: i64) -> i64 {
+    if b < 0 {
+        (-a).div_euclid(-b)
+    } else {
+        a.div_euclid(b)
+    }
+}
+
+#[cfg(feature = "native-ilp")]
+fn ceil_div(a: i64, b: i64) -> i64 {
+    -floor_div(-a, b)
+}
+
+// Find the fewest button presses so the counters reach exactly their joltages
+// This is an integer linear program: one equation per counter, one variable per button.
+// Gauss-Jordan elimination on integer rows expresses every pivot button
+// by the remaining free buttons, usually only a few of them are left.
+// A button can't be pressed more often than the lowest joltage of its counters.
+// The free buttons are searched one by one, each only within the presses
+// that keep every pivot button within its bounds, whatever the remaining ones do.
+// A lower bound of the total cuts off branches that can't beat the best plan
+// Like for the lights, every row remembers the counters it is combined from
+// Returns the number of presses for every button
+#[cfg(feature = "native-ilp")]
+fn solve_joltages(buttons: &[Vec<bool>], joltages: &[u32]) -> Result<Vec<u64>, Unsolvable> {
+    let n = buttons.len();
+    let m = joltages.len();
+    // each row holds the button coefficients, the joltage behind them
+    // and the multiple of every counter's original row summed up in it
+    let mut rows = joltages
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            let mut row = buttons.iter().map(|b| b[i] as i64).collect::<Vec<_>>();
+            row.push(*t as i64);
+            row.extend((0..m).map(|j| (i == j) as i64));
+            row
+        })
+        .collect::<Vec<_>>();
+
+    let mut pivots = Vec::new();
+    for col in 0..n {
+        let r = pivots.len();
+        let Some(p) = (r..rows.len()).find(|i| rows[*i][col] != 0) else {
+            continue;
+        };
+        rows.swap(r, p);
+        for i in 0..rows.len() {
+            if i == r || rows[i][col] == 0 {
+                continue;
+            }
+            let (a, b) = (rows[r][col], rows[i][col]);
+            let pivot_row = rows[r].clone();
+            for (x, y) in rows[i].iter_mut().zip(pivot_row) {
+                *x = *x * a - y * b;
+            }
+            reduce(&mut rows[i]);
+        }
+        pivots.push(col);
+    }
+
+    // a remaining row 0 = c has no solution unless c is zero
+    if let Some(r) = (pivots.len()..rows.len()).find(|r| rows[*r][n] != 0) {
+        let sources = (0..m).filter(|j| rows[r][n + 1 + j] != 0).collect();
+        return Err(Unsolvable::Counters(sources));
+    }
+    for row in &mut rows {
+        row.truncate(n + 1);
+        reduce(row);
+    }
+
+    let free = (0..n).filter(|c| !pivots.contains(c)).collect::<Vec<_>>();
+    let bounds = buttons
+        .iter()
+        .map(|b| {
+            (0..joltages.len())
+                .filter(|i| b[*i])
+                .map(|i| joltages[i] as i64)
+                .min()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    // pivot coefficients are made positive, so row[n] - sum of the free terms
+    // is the non negative pivot presses times row[p], bounded by the pivot button itself
+    for (r, p) in pivots.iter().enumerate() {
+        if rows[r][*p] < 0 {
+            rows[r].iter_mut().for_each(|x| *x = -*x);
+        }
+    }
+    let rows = &rows[..pivots.len()];
+    let targets = rows
+        .iter()
+        .zip(&pivots)
+        .map(|(row, p)| (row[n] - row[*p] * bounds[*p], row[n]))
+        .collect::<Vec<_>>();
+
+    // range of every row's free terms over the free buttons from k on
+    let mut rest = vec![vec![(0, 0); rows.len()]; free.len() + 1];
+    for k in (0..free.len()).rev() {
+        for (r, row) in rows.iter().enumerate() {
+            let (min, max) = rest[k + 1][r];
+            let term = row[free[k]] * bounds[free[k]];
+            rest[k][r] = (min + term.min(0), max + term.max(0));
+        }
+    }
+
+    // Every press of a free button changes the total by its weight,
+    // one for itself minus the presses it takes from the pivot buttons.
+    // The most the remaining free buttons can still save bounds the total from below
+    let weights = free
+        .iter()
+        .map(|f| {
+            1.0 - rows
+                .iter()
+                .zip(&pivots)
+                .map(|(row, p)| row[*f] as f64 / row[*p] as f64)
+                .sum::<f64>()
+        })
+        .collect::<Vec<_>>();
+    let mut savings = vec![0.0; free.len() + 1];
+    for k in (0..free.len()).rev() {
+        savings[k] = savings[k + 1] + weights[k].min(0.0) * bounds[free[k]] as f64;
+    }
+
+    struct Search<'a> {
+        rows: &'a [Vec<i64>],
+        pivots: &'a [usize],
+        free: &'a [usize],
+        bounds: &'a [i64],
+        savings: &'a [f64],
+        // allowed range of the free terms of every row
+        targets: &'a [(i64, i64)],
+        rest: &'a [Vec<(i64, i64)>],
+        // free terms of the buttons chosen so far for every row
+        partial: Vec<i64>,
+        presses: Vec<i64>,
+        best: Option<(i64, Vec<i64>)>,
+    }
+
+    impl Search<'_> {
+        // Presses of the k-th free button, which keep every row within its target
+        // no matter how the remaining free buttons are chosen
+        fn range(&self, k: usize) -> (i64, i64) {
+            let f = self.free[k];
+            let (mut lo, mut hi) = (0, self.bounds[f]);
+            for (r, row) in self.rows.iter().enumerate() {
+                let (min, max) = self.rest[k + 1][r];
+                let low = self.targets[r].0 - self.partial[r] - max;
+                let high = self.targets[r].1 - self.partial[r] - min;
+                match row[f] {
+                    0 if low > 0 || high < 0 => return (1, 0),
+                    0 => (),
+                    c if c > 0 => {
+                        lo = lo.max(ceil_div(low, c));
+                        hi = hi.min(floor_div(high, c));
+                    }
+                    c => {
+                        lo = lo.max(ceil_div(high, c));
+                        hi = hi.min(floor_div(low, c));
+                    }
+                }
+            }
+            (lo, hi)
+        }
+
+        // Lowest total reachable from the buttons chosen so far
+        fn lower_bound(&self, k: usize, free_sum: i64) -> f64 {
+            let n = self.presses.len();
+            let pivot_sum = self
+                .rows
+                .iter()
+                .zip(self.pivots)
+                .enumerate()
+                .map(|(r, (row, p))| (row[n] - self.partial[r]) as f64 / row[*p] as f64)
+                .sum::<f64>();
+            free_sum as f64 + pivot_sum + self.savings[k]
+        }
+
+        fn run(&mut self, k: usize, free_sum: i64) {
+            // the totals are whole numbers, allow for rounding errors of the bound
+            if self
+                .best
+                .as_ref()
+                .is_some_and(|(b, _)| self.lower_bound(k, free_sum) > *b as f64 - 1.0 + 1e-6)
+            {
+                return;
+            }
+            if k < self.free.len() {
+                let f = self.free[k];
+                let (lo, hi) = self.range(k);
+                for x in lo..=hi {
+                    // the pivot buttons can only add presses
+                    if self.best.as_ref().is_some_and(|(b, _)| free_sum + x >= *b) {
+                        break;
+                    }
+                    self.presses[f] = x;
+                    for (r, row) in self.rows.iter().enumerate() {
+                        self.partial[r] += row[f] * x;
                     }
-                    new_lights.push(light);
+                    self.run(k + 1, free_sum + x);
+                    for (r, row) in self.rows.iter().enumerate() {
+                        self.partial[r] -= row[f] * x;
+                    }
+                }
+                self.presses[f] = 0;
+                return;
+            }
+            let n = self.presses.len();
+            let mut sum = free_sum;
+            for (r, p) in self.pivots.iter().enumerate() {
+                let rest = self.rows[r][n] - self.partial[r];
+                if rest % self.rows[r][*p] != 0 {
+                    return;
                 }
+                self.presses[*p] = rest / self.rows[r][*p];
+                sum += self.presses[*p];
             }
-            lights = new_lights;
+            if self.best.as_ref().is_none_or(|(b, _)| sum < *b) {
+                self.best = Some((sum, self.presses.clone()));
+            }
+        }
+    }
+
+    // without free buttons the pivots alone have to be within their bounds
+    if free.is_empty() && targets.iter().any(|(lo, hi)| *lo > 0 || *hi < 0) {
+        return Err(Unsolvable::NoWholePlan);
+    }
+    let mut search = Search {
+        rows,
+        pivots: &pivots,
+        free: &free,
+        bounds: &bounds,
+        savings: &savings,
+        targets: &targets,
+        rest: &rest,
+        partial: vec![0; rows.len()],
+        presses: vec![0; n],
+        best: None,
+    };
+    search.run(0, 0);
+    search
+        .best
+        .map(|(_, presses)| presses.iter().map(|x| *x as u64).collect())
+        .ok_or(Unsolvable::NoWholePlan)
+}
+
+// Solve the same problem as an LP through the good_lp crate
+// The backend is chosen by the enabled lp-* feature
+// The solver works with floats, so every press count is rounded to the nearest integer
+// The solver can't name the conflicting counters, an infeasible problem is reported as a whole
+// Returns the number of presses for every button
+#[cfg(feature = "lp")]
+fn solve_joltages_lp(buttons: &[Vec<bool>], joltages: &[u32]) -> Result<Vec<u64>, Unsolvable> {
+    use good_lp::*;
+
+    // The problem variables are the number of presses for each button
+    // Each variable can be zero or a positive integer
+    let mut vars = variables!();
+    let variables = (0..buttons.len())
+        .map(|_| vars.add(variable().integer().min(0)))
+        .collect::<Vec<_>>();
+
+    // The expression to minimize is the sum of the variables
+    let objective = variables.iter().sum::<Expression>();
+    let mut problem = vars.minimise(&objective).using(default_solver);
+
+    // Each joltage value is a constraint to the problem
+    // The equation is given by the button properties
+    for (j, t) in joltages.iter().enumerate() {
+        let expr = variables
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| if buttons[i][j] { Some(x) } else { None })
+            .sum::<Expression>();
+        problem = problem.with(expr.eq(*t));
+    }
+
+    // Solve the lp
+    let solution = match problem.solve() {
+        Ok(solution) => solution,
+        Err(ResolutionError::Infeasible) => return Err(Unsolvable::NoWholePlan),
+        Err(e) => panic!("LP backend failed: {e}"),
+    };
+    Ok(variables
+        .iter()
+        .map(|x| solution.value(*x).round().max(0.0) as u64)
+        .collect())
+}
+
+// Joltage of every counter after pressing each button the given number of times
+fn joltages_after(buttons: &[Vec<bool>], presses: &[u64], counters: usize) -> Vec<u64> {
+    (0..counters)
+        .map(|i| {
+            buttons
+                .iter()
+                .zip(presses)
+                .filter(|(b, _)| b[i])
+                .map(|(_, p)| p)
+                .sum()
+        })
+        .collect()
+}
+
+// Press plan in the notation of the input, e.g. `2x(0,2) 1x(3)`
+fn format_plan(buttons: &[Vec<bool>], presses: &[u64]) -> String {
+    buttons
+        .iter()
+        .zip(presses)
+        .filter(|(_, p)| **p > 0)
+        .map(|(b, p)| format!("{p}x{}", format_button(b)))
+        .join(" ")
+}
+
+// Never trust the solver, the plan has to hit every joltage exactly
+fn check_plan(
+    buttons: &[Vec<bool>],
+    joltages: &[u32],
+    presses: Vec<u64>,
+) -> Result<Vec<u64>, Unsolvable> {
+    let reached = joltages_after(buttons, &presses, joltages.len());
+    if reached.iter().zip(joltages).all(|(a, b)| *a == *b as u64) {
+        Ok(presses)
+    } else {
+        Err(Unsolvable::MissedJoltages(reached))
+    }
+}
+
+// Fewest presses for every button of one machine
+// The LP backend is only a cross check if the native solver is enabled,
+// both may find different plans with the same total, but have to agree on unsolvable machines
+// None when no solver is enabled
+#[cfg(feature = "native-ilp")]
+fn press_plan(buttons: &[Vec<bool>], joltages: &[u32]) -> Option<Result<Vec<u64>, Unsolvable>> {
+    let presses = solve_joltages(buttons, joltages);
+
+    #[cfg(feature = "lp")]
+    {
+        let native = presses.as_ref().ok().map(|p| p.iter().sum::<u64>());
+        let lp = solve_joltages_lp(buttons, joltages)
+            .ok()
+            .map(|p| p.iter().sum::<u64>());
+        if native != lp {
+            return Some(Err(Unsolvable::SolversDisagree(native, lp)));
         }
-        levels.push(level);
     }
 
-    let sum: i32 = levels.iter().sum();
+    Some(presses)
+}
+
+#[cfg(all(feature = "lp", not(feature = "native-ilp")))]
+fn press_plan(buttons: &[Vec<bool>], joltages: &[u32]) -> Option<Result<Vec<u64>, Unsolvable>> {
+    Some(solve_joltages_lp(buttons, joltages))
+}
 
-    assert_eq!(sum, 441);
-    println!("Part 1: {sum}");
+#[cfg(not(any(feature = "native-ilp", feature = "lp")))]
+fn press_plan(_: &[Vec<bool>], _: &[u32]) -> Option<Result<Vec<u64>, Unsolvable>> {
+    None
 }
 
+// Print the press plan of every machine with `--plan`
+// Unsolvable machines and failed solver checks are reported and left out of the sum
 pub fn part_2() {
     let data = get_data();
+    let print_plan = has_arg("--plan");
 
     let mut clicks = Vec::new();
+    let mut unsolvable = 0;
 
-    for (_, buttons, joltages) in data.iter() {
-        // Solve linear system using the good_lp crate
-        // This crate supports integer problems
+    for (m, (_, buttons, joltages)) in data.iter().enumerate() {
+        let Some(presses) = press_plan(buttons, joltages) else {
+            println!(
+                "Part 2: skipped, no solver for the joltages enabled. \
+                Build with the native-ilp feature or one of lp-scip, lp-microlp, lp-highs and lp-cbc"
+            );
+            return;
+        };
+        let presses = match presses.and_then(|p| check_plan(buttons, joltages, p)) {
+            Ok(presses) => presses,
+            Err(e) => {
+                println!("Machine {m} {}: skipped, {e}", format_machine(&data[m]));
+                unsolvable += 1;
+                continue;
+            }
+        };
 
-        // The problem variables are the number of presses for each button
-        // Each variable can be zero or a positive integer
-        let mut vars = variables!();
-        let variables = (0..buttons.len())
-            .map(|_| vars.add(variable().integer().min(0)))
-            .collect::<Vec<_>>();
+        if print_plan {
+            println!("Machine {m}: {}", format_plan(buttons, &presses));
+        }
+
+        clicks.push(presses.iter().sum::<u64>());
+    }
 
-        // The expression to minimize is the sum of the variables
-        let objective = variables.iter().sum::<Expression>();
-        let mut problem = vars.minimise(&objective).using(default_solver);
+    let sum = clicks.iter().sum::<u64>();
 
-        // Each joltage value is a constraint to the problem
-        // The equation is given by the button properties
-        for (j, t) in joltages.iter().enumerate() {
-            let expr = variables
-                .iter()
-                .enumerate()
-                .filter_map(|(i, x)| if buttons[i][j] { Some(x) } else { None })
-                .sum::<Expression>();
-            problem = problem.with(expr.eq(*t));
+    if unsolvable == 0 {
+        assert_eq!(sum, 18559);
+        println!("Part 2: {sum}");
+    } else {
+        println!("Part 2: {sum} ({unsolvable} machines skipped)");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_round_trip() {
+        let machines = parse_machines(TEST_DATA).unwrap();
+        assert_eq!(machines.len(), 3);
+        let formatted = machines.iter().map(format_machine).join("\n");
+        assert_eq!(formatted, TEST_DATA.trim());
+        assert_eq!(parse_machines(&formatted), Ok(machines));
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let error = |data: &str| parse_machines(data).unwrap_err();
+        assert_eq!(
+            error("[.#] (0) {1,2}\n\n[.#] (0) (5) {1,2}"),
+            ParseError {
+                line: 3,
+                kind: ParseErrorKind::ButtonOutOfRange(1, 5, 2)
+            }
+        );
+        assert_eq!(
+            error("[.#] (0,0) {1,2}").kind,
+            ParseErrorKind::DuplicateIndex(0, 0)
+        );
+        assert_eq!(
+            error("[.#] (0) {1}").kind,
+            ParseErrorKind::JoltageCount(2, 1)
+        );
+        assert_eq!(error("[.x] {1,2}").kind, ParseErrorKind::InvalidLight('x'));
+        assert_eq!(error("[] {}").kind, ParseErrorKind::NoLights);
+        assert_eq!(error("(0) {1}").kind, ParseErrorKind::MissingLights);
+        assert_eq!(error("[.#] (0)").kind, ParseErrorKind::MissingJoltages);
+        assert_eq!(error("[.#] () {1,2}").kind, ParseErrorKind::EmptyButton(0));
+        assert_eq!(
+            error("[.#] (a) {1,2}").kind,
+            ParseErrorKind::InvalidNumber("a".to_string())
+        );
+        assert_eq!(
+            error("[.#] (0) x {1,2}").kind,
+            ParseErrorKind::UnexpectedToken("x".to_string())
+        );
+    }
+
+    #[test]
+    fn unsolvable_lights() {
+        let buttons = vec![vec![true, true, false], vec![true, true, false]];
+        assert_eq!(
+            solve_lights(&[false, false, true], &buttons),
+            Err(Unsolvable::Lights(vec![2]))
+        );
+        assert_eq!(
+            solve_lights(&[true, false, false], &buttons),
+            Err(Unsolvable::Lights(vec![0, 1]))
+        );
+        assert_eq!(
+            solve_lights(&[true, true, false], &buttons).unwrap().len(),
+            1
+        );
+        // light 0 is combined into both conflicting rows and cancels out
+        let buttons = vec![vec![true, true, true], vec![false, true, true]];
+        assert_eq!(
+            solve_lights(&[false, true, false], &buttons),
+            Err(Unsolvable::Lights(vec![1, 2]))
+        );
+    }
+
+    // More buttons than the free button combinations can be tried for
+    #[test]
+    fn many_buttons() {
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = |m: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % m
+        };
+        let mut machine = |lights: usize, buttons: usize| {
+            let buttons = (0..buttons)
+                .map(|_| (0..lights).map(|_| random(3) == 0).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            // the lights after pressing a random button
+            let target = buttons[random(buttons.len() as u64) as usize].clone();
+            (target, buttons)
+        };
+
+        for _ in 0..5 {
+            let (target, buttons) = machine(5, 36);
+            let presses = solve_lights(&target, &buttons).unwrap();
+            let lights = |presses: &[usize]| {
+                (0..target.len())
+                    .map(|i| presses.iter().filter(|b| buttons[**b][i]).count() % 2 == 1)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(lights(&presses), target);
+            // no smaller combination of buttons reaches the target
+            for k in 0..presses.len() {
+                assert!(
+                    (0..buttons.len())
+                        .combinations(k)
+                        .all(|c| lights(&c) != target)
+                );
+            }
+        }
+
+        // both searches agree where the free buttons are tried one by one
+        for _ in 0..20 {
+            let (target, buttons) = machine(8, 12);
+            assert_eq!(
+                solve_lights(&target, &buttons).unwrap().len(),
+                fewest_presses(&target, &buttons).len()
+            );
         }
 
-        // Solve the lp
-        let solution = problem.solve().unwrap();
-        clicks.push(solution.eval(objective));
+        let (target, buttons) = machine(25, 60);
+        assert!(matches!(
+            solve_lights(&target, &buttons),
+            Err(Unsolvable::TooManyFreeButtons(k)) if k >= 35
+        ));
     }
 
-    let sum = clicks.iter().sum::<f64>() as i32;
+    #[cfg(feature = "native-ilp")]
+    #[test]
+    fn unsolvable_joltages() {
+        let buttons = vec![vec![true, true, false], vec![true, true, false]];
+        assert_eq!(
+            solve_joltages(&buttons, &[1, 1, 3]),
+            Err(Unsolvable::Counters(vec![2]))
+        );
+        assert_eq!(
+            solve_joltages(&buttons, &[1, 2, 0]),
+            Err(Unsolvable::Counters(vec![0, 1]))
+        );
+        let buttons = vec![vec![true, true, true], vec![false, true, true]];
+        assert_eq!(
+            solve_joltages(&buttons, &[0, 1, 2]),
+            Err(Unsolvable::Counters(vec![1, 2]))
+        );
+        let buttons = vec![vec![true, true], vec![false, true]];
+        assert_eq!(
+            solve_joltages(&buttons, &[2, 1]),
+            Err(Unsolvable::NoWholePlan)
+        );
+    }
+
+    #[test]
+    fn plans_missing_the_joltages_are_rejected() {
+        let buttons = vec![vec![true, true], vec![false, true]];
+        assert_eq!(check_plan(&buttons, &[2, 3], vec![2, 1]), Ok(vec![2, 1]));
+        assert_eq!(
+            check_plan(&buttons, &[2, 3], vec![1, 1]),
+            Err(Unsolvable::MissedJoltages(vec![1, 2]))
+        );
+    }
 
-    assert_eq!(sum, 18559);
-    println!("Part 2: {sum}");
+    #[cfg(any(feature = "native-ilp", feature = "lp"))]
+    #[test]
+    fn test_data_joltages() {
+        let machines = parse_machines(TEST_DATA).unwrap();
+        let sums = machines
+            .iter()
+            .map(|(_, buttons, joltages)| press_plan(buttons, joltages).unwrap().unwrap())
+            .map(|p| p.iter().sum::<u64>())
+            .collect::<Vec<_>>();
+        assert_eq!(sums, vec![10, 12, 11]);
+    }
+
+    // Machines of the puzzle size with joltages from known press plans
+    #[cfg(feature = "native-ilp")]
+    #[test]
+    fn random_machines() {
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = |m: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % m
+        };
+        for _ in 0..50 {
+            let counters = 4 + random(7) as usize;
+            let buttons = (0..(counters + random(5) as usize).min(13))
+                .map(|_| {
+                    let mut b = vec![false; counters];
+                    for _ in 0..=random(counters as u64) {
+                        b[random(counters as u64) as usize] = true;
+                    }
+                    b
+                })
+                .collect::<Vec<_>>();
+            let known = (0..buttons.len()).map(|_| random(40)).collect::<Vec<_>>();
+            let joltages = joltages_after(&buttons, &known, counters)
+                .iter()
+                .map(|j| *j as u32)
+                .collect::<Vec<_>>();
+
+            let presses = solve_joltages(&buttons, &joltages).unwrap();
+            assert_eq!(
+                joltages_after(&buttons, &presses, counters),
+                joltages.iter().map(|j| *j as u64).collect::<Vec<_>>()
+            );
+            assert!(presses.iter().sum::<u64>() <= known.iter().sum::<u64>());
+        }
+    }
 }
@@ -1,6 +1,7 @@
-use std::collections::HashMap;
-
-use crate::utils::get_input;
+use crate::{
+    dag::{Cycle, Graph},
+    utils::{get_input, has_arg},
+};
 
 const USE_TEST_DATA: bool = false;
 
@@ -41,7 +42,7 @@ fn device(name: &str) -> Device {
     name.as_bytes().try_into().unwrap()
 }
 
-fn get_data(part: usize) -> HashMap<Device, Vec<Device>> {
+fn get_data(part: usize) -> Graph<Device> {
     let data_str = if USE_TEST_DATA {
         if part == 1 {
             TEST_DATA_PART_1.to_string()
@@ -52,76 +53,94 @@ fn get_data(part: usize) -> HashMap<Device, Vec<Device>> {
         get_input(11).unwrap()
     };
 
-    data_str
-        .trim()
-        .split("\n")
-        .map(|r| {
-            let (k, v) = r.split_once(": ").unwrap();
-            (device(k), v.split_whitespace().map(device).collect())
-        })
-        .collect()
+    Graph::new(data_str.trim().split("\n").map(|r| {
+        let (k, v) = r.split_once(": ").unwrap();
+        (device(k), v.split_whitespace().map(device).collect())
+    }))
 }
 
-// use recursion with caching
-// Calculate the number of possible paths between two devices
-// walking backwards from target to start
-fn walk(
-    data: &HashMap<Device, Vec<Device>>,
-    cache: &mut HashMap<Device, u64>,
-    start: &Device,
-    target: &Device,
-) -> u64 {
-    // When target is reached return 1
-    // Recursion base case
-    if start == target {
-        return 1;
-    }
-    // On cache hit return cached value
-    if let Some(cached_sum) = cache.get(start) {
-        return *cached_sum;
+// Loop of devices as `aaa -> bbb -> aaa`
+fn format_cycle(cycle: &Cycle<Device>) -> String {
+    cycle
+        .nodes
+        .iter()
+        .chain(cycle.nodes.first())
+        .map(|d| String::from_utf8_lossy(d))
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+// With a loop in the wiring there are infinitely many paths
+// Report the loop and tell whether only the simple paths,
+// visiting every device at most once, should be counted instead
+fn report_loop(cycle: &Cycle<Device>) -> bool {
+    println!(
+        "The device wiring contains the loop {}",
+        format_cycle(cycle)
+    );
+    let simple_paths = has_arg("--simple-paths");
+    if !simple_paths {
+        println!("Rerun with --simple-paths to count the paths without loops");
     }
-    // get connected devices and calculate the sum of possible path recursively
-    // zero when no device is connected
-    let sum = match data.get(start) {
-        Some(connections) => connections
-            .iter()
-            .map(|next| walk(data, cache, next, target))
-            .sum(),
-        None => 0,
-    };
-    // cache calculated sum
-    cache.insert(*start, sum);
-    sum
+    simple_paths
 }
 
 pub fn part_1() {
-    let data = get_data(1);
-
-    // simply walk all paths from "you" to "out"
-    let sum = walk(&data, &mut HashMap::new(), &device("you"), &device("out"));
+    let graph = get_data(1);
+
+    let [you, out] = ["you", "out"].map(|d| graph.index(&device(d)).unwrap());
+
+    // simply count all paths from "you" to "out"
+    let sum = match graph.dag_between(you, out) {
+        Ok(dag) => dag.paths(you, out),
+        Err(cycle) => {
+            if report_loop(&cycle) {
+                let sum = graph.simple_paths(you, out, &[]);
+                println!("Part 1: {sum} simple paths");
+            } else {
+                println!("Part 1: skipped");
+            }
+            return;
+        }
+    };
 
     assert_eq!(sum, 636);
     println!("Part 1: {sum}");
 }
 
 pub fn part_2() {
-    let data = get_data(2);
+    let graph = get_data(2);
+    let [svr, fft, dac, out] =
+        ["svr", "fft", "dac", "out"].map(|d| graph.index(&device(d)).unwrap());
+
+    // only loops on the way from svr to out matter
+    let data = match graph.dag_between(svr, out) {
+        Ok(dag) => dag,
+        Err(cycle) => {
+            // the paths can't be split at the stations,
+            // two parts of a simple path must not share a device
+            if report_loop(&cycle) {
+                let sum = graph.simple_paths(svr, out, &[fft, dac]);
+                println!("Part 2: {sum} simple paths");
+            } else {
+                println!("Part 2: skipped");
+            }
+            return;
+        }
+    };
 
-    // calculate the possible paths from one station to another
+    // count the paths from each station to all other devices
     // multiplying the options for each part gives the full number of possible paths
+    let from_svr = data.paths_from(svr);
+    let from_fft = data.paths_from(fft);
+    let from_dac = data.paths_from(dac);
+
     // svr -> fft -> dac -> out
-    let srv_fft = walk(&data, &mut HashMap::new(), &device("svr"), &device("fft"));
-    let fft_dac = walk(&data, &mut HashMap::new(), &device("fft"), &device("dac"));
-    let dac_out = walk(&data, &mut HashMap::new(), &device("dac"), &device("out"));
-    let svr_fft_dac_out = srv_fft * fft_dac * dac_out;
+    let svr_fft_dac_out = from_svr[fft] * from_fft[dac] * from_dac[out];
 
     // svr -> dac -> fft -> out
     // dac -> fft is actually 0 in the given data
-    // so this part could be skipped in this case
-    let srv_dac = walk(&data, &mut HashMap::new(), &device("svr"), &device("dac"));
-    let dac_fft = walk(&data, &mut HashMap::new(), &device("dac"), &device("fft"));
-    let fft_out = walk(&data, &mut HashMap::new(), &device("fft"), &device("out"));
-    let svr_dac_fft_out = srv_dac * dac_fft * fft_out;
+    let svr_dac_fft_out = from_svr[dac] * from_dac[fft] * from_fft[out];
 
     // Adding both possible combinations gives the absolute number
     let sum = svr_fft_dac_out + svr_dac_fft_out;
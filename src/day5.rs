@@ -1,6 +1,9 @@
 use std::ops::RangeInclusive;
 
-use crate::utils::get_input;
+use crate::{
+    interval_set::{IntervalSet, find_sources},
+    utils::{get_input, has_arg},
+};
 
 #[allow(dead_code)]
 const TEST_DATA: &str = r#"
@@ -37,36 +40,39 @@ fn get_data() -> (Vec<RangeInclusive<i64>>, Vec<i64>) {
     (ranges, values)
 }
 
+// Print the input range making each ingredient fresh with `--fresh`
 pub fn part_1() {
     let (ranges, values) = get_data();
 
-    let count = values
-        .iter()
-        .filter(|x| ranges.iter().any(|r| r.contains(x)))
-        .count();
+    if has_arg("--fresh") {
+        for (id, source) in values.iter().zip(find_sources(&ranges, &values)) {
+            match source {
+                Some(r) => println!(
+                    "Ingredient {id}: fresh by range {r} ({}-{})",
+                    ranges[r].start(),
+                    ranges[r].end()
+                ),
+                None => println!("Ingredient {id}: spoiled"),
+            }
+        }
+    }
+
+    // merge the ranges and sweep all sorted ids against them at once
+    // every id matching a range is fresh
+    let fresh: IntervalSet = ranges.into_iter().collect();
+
+    let count = fresh.find_all(&values).iter().flatten().count();
 
     assert_eq!(count, 737);
     println!("Part 1: {count:?}");
 }
 
 pub fn part_2() {
-    let (mut ranges, _) = get_data();
-
-    ranges.sort_by(|a, b| a.start().cmp(b.start()));
+    let (ranges, _) = get_data();
 
-    let mut prev_end = 0;
-
-    let sum: i64 = ranges
-        .iter()
-        .map(|r| {
-            if r.end() <= &prev_end {
-                return 0;
-            }
-            let start = *r.start().max(&(prev_end + 1));
-            prev_end = *r.end();
-            r.end() - start + 1
-        })
-        .sum();
+    // overlapping ranges are merged on insert,
+    // so the total length counts every id only once
+    let sum = ranges.into_iter().collect::<IntervalSet>().total_len();
 
     assert_eq!(sum, 357485433193284);
     println!("Part 2: {sum}");
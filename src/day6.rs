@@ -1,4 +1,6 @@
-use crate::utils::get_input;
+use std::{fmt::Display, ops::Range, str::FromStr};
+
+use crate::utils::{get_arg, get_input};
 
 #[allow(dead_code)]
 const TEST_DATA: &str = r#"
@@ -15,80 +17,394 @@ fn get_data() -> String {
     data_str.to_string()
 }
 
-pub fn part_1() {
-    let data_str = get_data();
-    let data = data_str
-        .trim()
-        .split("\n")
-        .map(|r| r.split_whitespace().collect::<Vec<_>>())
-        .collect::<Vec<_>>();
+// How the numbers of a problem are written on the worksheet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reading {
+    // one number per row, read left to right
+    Horizontal,
+    // one number per column, read top to bottom, columns right to left
+    Vertical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Min,
+    Max,
+}
+
+impl FromStr for Operator {
+    type Err = EvalError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "+" => Ok(Self::Add),
+            "-" => Ok(Self::Sub),
+            "*" => Ok(Self::Mul),
+            "/" => Ok(Self::Div),
+            "%" => Ok(Self::Rem),
+            "^" => Ok(Self::Pow),
+            "min" => Ok(Self::Min),
+            "max" => Ok(Self::Max),
+            _ => Err(EvalError::UnknownOperator(value.to_string())),
+        }
+    }
+}
 
-    let cols = data[0].len();
-    let rows = data.len();
+impl Operator {
+    // The conventional associativity, only exponentiation groups from the right
+    fn associativity(&self) -> Associativity {
+        match self {
+            Self::Pow => Associativity::Right,
+            _ => Associativity::Left,
+        }
+    }
 
-    let mut nums: Vec<i64> = Vec::with_capacity(rows - 1);
+    // Apply the operator to two operands
+    // using checked arithmetic instead of silently wrapping
+    fn apply(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        match self {
+            Self::Add => a.checked_add(b).ok_or(EvalError::Overflow),
+            Self::Sub => a.checked_sub(b).ok_or(EvalError::Overflow),
+            Self::Mul => a.checked_mul(b).ok_or(EvalError::Overflow),
+            Self::Div | Self::Rem if b == 0 => Err(EvalError::DivisionByZero),
+            Self::Div => a.checked_div(b).ok_or(EvalError::Overflow),
+            Self::Rem => a.checked_rem(b).ok_or(EvalError::Overflow),
+            Self::Pow if b < 0 => Err(EvalError::NegativeExponent),
+            // huge exponents still work for the bases 0, 1 and -1
+            Self::Pow => a
+                .checked_pow(u32::try_from(b).unwrap_or(u32::MAX))
+                .ok_or(EvalError::Overflow),
+            Self::Min => Ok(a.min(b)),
+            Self::Max => Ok(a.max(b)),
+        }
+    }
+}
+
+// The order in which the operator is applied to more than two operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Associativity {
+    // ((a op b) op c)
+    Left,
+    // (a op (b op c))
+    Right,
+}
+
+// Associativity for all operators from `--associativity left|right`
+// None keeps the default of each operator
+fn associativity_arg() -> Option<Associativity> {
+    match get_arg("--associativity").as_deref() {
+        Some("left") => Some(Associativity::Left),
+        Some("right") => Some(Associativity::Right),
+        Some(a) => panic!("Unsupported associativity {a:?}"),
+        None => None,
+    }
+}
 
-    let sum = (0..cols)
-        .map(|i| {
-            nums.clear();
-            for d in data.iter().take(rows - 1) {
-                nums.push(d[i].parse().unwrap());
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EvalError {
+    Overflow,
+    DivisionByZero,
+    NegativeExponent,
+    NoOperands,
+    UnknownOperator(String),
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Overflow => write!(f, "arithmetic overflow"),
+            Self::DivisionByZero => write!(f, "division by zero"),
+            Self::NegativeExponent => write!(f, "negative exponent"),
+            Self::NoOperands => write!(f, "no operands"),
+            Self::UnknownOperator(op) => write!(f, "unknown operator {op:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Problem {
+    operands: Vec<i64>,
+    operator: Operator,
+    // the worksheet columns covered by the problem
+    column_span: Range<usize>,
+}
+
+impl Problem {
+    fn evaluate(&self, associativity: Associativity) -> Result<i64, EvalError> {
+        match associativity {
+            Associativity::Left => {
+                let (first, rest) = self.operands.split_first().ok_or(EvalError::NoOperands)?;
+                rest.iter()
+                    .try_fold(*first, |acc, x| self.operator.apply(acc, *x))
             }
-            match data[rows - 1][i] {
-                "+" => nums.iter().sum::<i64>(),
-                "*" => nums.iter().product::<i64>(),
-                _ => panic!("Unsupported operation"),
+            Associativity::Right => {
+                let (last, rest) = self.operands.split_last().ok_or(EvalError::NoOperands)?;
+                rest.iter()
+                    .rev()
+                    .try_fold(*last, |acc, x| self.operator.apply(*x, acc))
+            }
+        }
+    }
+
+    // Evaluate the problem with the given associativity,
+    // the operators default one if None
+    fn solve(&self, associativity: Option<Associativity>) -> Result<i64, EvalError> {
+        self.evaluate(associativity.unwrap_or(self.operator.associativity()))
+    }
+}
+
+// Sum the results of all problems and the number of skipped problems
+// A problem which fails to evaluate is reported and left out of the sum
+fn grand_total(problems: &[Problem], associativity: Option<Associativity>) -> (i64, usize) {
+    let mut sum = 0;
+    let mut skipped = 0;
+    for p in problems {
+        match p.solve(associativity) {
+            Ok(result) => sum += result,
+            Err(e) => {
+                println!(
+                    "Problem in columns {:?} ({:?} {:?}): skipped, {e}",
+                    p.column_span, p.operator, p.operands
+                );
+                skipped += 1;
+            }
+        }
+    }
+    (sum, skipped)
+}
+
+// Parse the digits of a row or column ignoring blanks
+// None when there are no digits at all
+fn read_number(cells: impl Iterator<Item = char>) -> Option<i64> {
+    let num_str = cells.filter(|c| *c != ' ').collect::<String>();
+    (!num_str.is_empty()).then(|| num_str.parse().unwrap())
+}
+
+// Split the worksheet into problems
+// Problems are separated by columns, which are blank in every row
+// Rows may differ in length, missing cells count as blank
+fn parse_worksheet(data: &str, reading: Reading) -> Vec<Problem> {
+    let mut rows = data
+        .lines()
+        .filter(|r| !r.trim().is_empty())
+        .map(|r| r.chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let ops = rows.pop().unwrap();
+
+    let width = rows.iter().chain([&ops]).map(|r| r.len()).max().unwrap();
+    let cell = |row: &[char], col: usize| row.get(col).copied().unwrap_or(' ');
+    let is_blank = |col: usize| rows.iter().chain([&ops]).all(|r| cell(r, col) == ' ');
+
+    // find the spans of consecutive non blank columns
+    let mut spans = Vec::new();
+    let mut start = None;
+    for col in 0..=width {
+        match (start, col == width || is_blank(col)) {
+            (None, false) => start = Some(col),
+            (Some(s), true) => {
+                spans.push(s..col);
+                start = None;
+            }
+            _ => (),
+        }
+    }
+
+    spans
+        .into_iter()
+        .map(|column_span| {
+            let operator = column_span
+                .clone()
+                .map(|col| cell(&ops, col))
+                .collect::<String>()
+                .trim()
+                .parse::<Operator>()
+                .unwrap_or_else(|e| panic!("Problem in columns {column_span:?}: {e}"));
+            let operands = match reading {
+                Reading::Horizontal => rows
+                    .iter()
+                    .filter_map(|r| read_number(column_span.clone().map(|col| cell(r, col))))
+                    .collect(),
+                Reading::Vertical => column_span
+                    .clone()
+                    .rev()
+                    .filter_map(|col| read_number(rows.iter().map(|r| cell(r, col))))
+                    .collect(),
+            };
+            Problem {
+                operands,
+                operator,
+                column_span,
             }
         })
-        .sum::<i64>();
+        .collect()
+}
 
-    assert_eq!(sum, 4771265398012);
-    println!("Part 1: {sum:?}");
+pub fn part_1() {
+    let data_str = get_data();
+    let associativity = associativity_arg();
+
+    let problems = parse_worksheet(&data_str, Reading::Horizontal);
+    let (sum, skipped) = grand_total(&problems, associativity);
+
+    if skipped == 0 {
+        // the answer only holds for the default associativity
+        if associativity.is_none() {
+            assert_eq!(sum, 4771265398012);
+        }
+        println!("Part 1: {sum:?}");
+    } else {
+        println!("Part 1: {sum} ({skipped} problems skipped)");
+    }
 }
 
 pub fn part_2() {
     let data_str = get_data();
-    let mut data = data_str.trim().split("\n").collect::<Vec<_>>();
-    let ops = data.pop().unwrap();
-
-    let mut calculations = Vec::new();
-    let mut operations = Vec::new();
-
-    let mut nums: Vec<i64> = Vec::new();
-    for i in 0..data[0].len() {
-        match ops.chars().nth(i) {
-            op @ (Some('+') | Some('*')) => {
-                if !nums.is_empty() {
-                    calculations.push(nums.clone());
-                }
-                nums.clear();
-                operations.push(op.unwrap());
-            }
-            Some(' ') | None => (),
-            Some(_) => panic!("Unsupported operation"),
+    let associativity = associativity_arg();
+
+    // same problems, but the numbers are written in columns
+    let problems = parse_worksheet(&data_str, Reading::Vertical);
+    let (sum, skipped) = grand_total(&problems, associativity);
+
+    if skipped == 0 {
+        if associativity.is_none() {
+            assert_eq!(sum, 10695785245101);
         }
+        println!("Part 2: {sum}");
+    } else {
+        println!("Part 2: {sum} ({skipped} problems skipped)");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let num_str = data
+    fn sum(data: &str, reading: Reading) -> i64 {
+        parse_worksheet(data, reading)
             .iter()
-            .map(|r| r.chars().nth(i).unwrap())
-            .filter(|c| c.is_numeric())
-            .collect::<String>();
-        if !num_str.is_empty() {
-            nums.push(num_str.parse().unwrap());
+            .map(|p| p.evaluate(p.operator.associativity()).unwrap())
+            .sum()
+    }
+
+    #[test]
+    fn test_data() {
+        assert_eq!(sum(TEST_DATA, Reading::Horizontal), 4277556);
+        assert_eq!(sum(TEST_DATA, Reading::Vertical), 3263827);
+    }
+
+    #[test]
+    fn trailing_whitespace_is_optional() {
+        let trimmed = TEST_DATA
+            .lines()
+            .map(|l| l.trim_end())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(sum(&trimmed, Reading::Horizontal), 4277556);
+        assert_eq!(sum(&trimmed, Reading::Vertical), 3263827);
+    }
+
+    #[test]
+    fn column_spans() {
+        let problems = parse_worksheet(TEST_DATA, Reading::Horizontal);
+        let spans = problems
+            .iter()
+            .map(|p| p.column_span.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![0..3, 4..7, 8..11, 12..15]);
+        assert_eq!(problems[0].operands, vec![123, 45, 6]);
+        assert_eq!(problems[3].operator, Operator::Add);
+    }
+
+    #[test]
+    fn ragged_rows_and_word_operators() {
+        let data = "12   70\n3    5\nmin max";
+        let horizontal = parse_worksheet(data, Reading::Horizontal);
+        assert_eq!(horizontal[0].column_span, 0..3);
+        assert_eq!(horizontal[1].column_span, 4..7);
+        assert_eq!(horizontal[0].operator, Operator::Min);
+        assert_eq!(horizontal[1].operator, Operator::Max);
+        assert_eq!(horizontal[0].operands, vec![12, 3]);
+        assert_eq!(horizontal[1].operands, vec![70, 5]);
+
+        // columns are read right to left, blank columns give no number
+        let vertical = parse_worksheet(data, Reading::Vertical);
+        assert_eq!(vertical[0].operands, vec![2, 13]);
+        assert_eq!(vertical[1].operands, vec![0, 75]);
+        assert_eq!(sum(data, Reading::Vertical), 2 + 75);
+    }
+
+    fn problem(operator: &str, operands: &[i64]) -> Problem {
+        Problem {
+            operands: operands.to_vec(),
+            operator: operator.parse().unwrap(),
+            column_span: 0..1,
         }
     }
-    calculations.push(nums.clone());
 
-    let sum = calculations
-        .iter()
-        .zip(operations.iter())
-        .map(|(calc, op)| match op {
-            '+' => calc.iter().sum::<i64>(),
-            '*' => calc.iter().product::<i64>(),
-            _ => panic!("Unsupported operation"),
-        })
-        .sum::<i64>();
+    fn eval(operator: &str, operands: &[i64]) -> Result<i64, EvalError> {
+        problem(operator, operands).solve(None)
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(eval("+", &[1, 2, 3]), Ok(6));
+        assert_eq!(eval("-", &[10, 3, 2]), Ok(5));
+        assert_eq!(eval("*", &[2, 3, 4]), Ok(24));
+        assert_eq!(eval("/", &[100, 5, 3]), Ok(6));
+        assert_eq!(eval("/", &[-7, 2]), Ok(-3));
+        assert_eq!(eval("%", &[17, 5]), Ok(2));
+        assert_eq!(eval("%", &[-7, 2]), Ok(-1));
+        assert_eq!(eval("^", &[2, 10]), Ok(1024));
+        assert_eq!(eval("^", &[-1, i64::MAX]), Ok(-1));
+        assert_eq!(eval("min", &[4, -2, 7]), Ok(-2));
+        assert_eq!(eval("max", &[4, -2, 7]), Ok(7));
+        assert_eq!(eval("+", &[42]), Ok(42));
+    }
 
-    assert_eq!(sum, 10695785245101);
-    println!("Part 2: {sum}");
+    #[test]
+    fn errors() {
+        assert_eq!(eval("+", &[i64::MAX, 1]), Err(EvalError::Overflow));
+        assert_eq!(eval("-", &[i64::MIN, 1]), Err(EvalError::Overflow));
+        assert_eq!(eval("*", &[i64::MAX, 2]), Err(EvalError::Overflow));
+        assert_eq!(eval("/", &[i64::MIN, -1]), Err(EvalError::Overflow));
+        assert_eq!(eval("^", &[10, 19]), Err(EvalError::Overflow));
+        assert_eq!(eval("/", &[1, 0]), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("%", &[1, 0]), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("^", &[2, -1]), Err(EvalError::NegativeExponent));
+        assert_eq!(eval("*", &[]), Err(EvalError::NoOperands));
+        assert_eq!(
+            "**".parse::<Operator>(),
+            Err(EvalError::UnknownOperator("**".to_string()))
+        );
+
+        // failing problems are left out of the total
+        let problems = [
+            problem("+", &[1, 2]),
+            problem("/", &[1, 0]),
+            problem("*", &[3, 4]),
+        ];
+        assert_eq!(grand_total(&problems, None), (15, 1));
+    }
+
+    #[test]
+    fn associativity() {
+        // exponentiation groups from the right by default
+        assert_eq!(eval("^", &[2, 3, 2]), Ok(512));
+        let pow = problem("^", &[2, 3, 2]);
+        assert_eq!(pow.solve(Some(Associativity::Left)), Ok(64));
+        assert_eq!(pow.solve(Some(Associativity::Right)), Ok(512));
+
+        let sub = problem("-", &[10, 3, 2]);
+        assert_eq!(sub.solve(Some(Associativity::Left)), Ok(5));
+        assert_eq!(sub.solve(Some(Associativity::Right)), Ok(9));
+        let div = problem("/", &[100, 10, 5]);
+        assert_eq!(div.solve(Some(Associativity::Left)), Ok(2));
+        assert_eq!(div.solve(Some(Associativity::Right)), Ok(50));
+    }
 }
@@ -1,4 +1,9 @@
-use crate::utils::get_input;
+use std::fmt::Display;
+
+use itertools::Itertools;
+use num_bigint::BigUint;
+
+use crate::utils::{get_arg, get_input};
 
 #[allow(dead_code)]
 const TEST_DATA: &str = r#"
@@ -20,119 +25,514 @@ const TEST_DATA: &str = r#"
 ...............
 "#;
 
-fn get_data() -> Vec<String> {
-    let data_str = get_input(7).unwrap();
-    // let data_str = TEST_DATA;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    const ALL: [Self; 4] = [Self::Up, Self::Down, Self::Left, Self::Right];
+
+    // (row, col) step when moving into this direction
+    fn delta(&self) -> (isize, isize) {
+        match self {
+            Self::Up => (-1, 0),
+            Self::Down => (1, 0),
+            Self::Left => (0, -1),
+            Self::Right => (0, 1),
+        }
+    }
 
-    data_str.trim().split("\n").map(str::to_string).collect()
+    fn from_delta(delta: (isize, isize)) -> Self {
+        *Self::ALL.iter().find(|d| d.delta() == delta).unwrap()
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
 }
 
-pub fn part_1() {
-    let mut data = get_data();
-
-    let rows = data.len();
-    let cols = data[0].len();
-
-    let mut count = 0;
-
-    // Draw rays into data by replacing the dots in place
-    for i in 1..rows {
-        for j in 0..cols {
-            let above = data[i - 1].chars().nth(j).unwrap();
-            let current = data[i].chars().nth(j).unwrap();
-            match (current, above) {
-                ('.', 'S') | ('.', '|') => data[i].replace_range(j..j + 1, "|"),
-                ('^', '|') => {
-                    count += 1;
-                    data[i].replace_range(j - 1..j + 2, "|^|");
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Cell {
+    Empty,
+    Source,
+    // Emits a beam for every offset, shifted sideways relative to the beam direction
+    // The beams continue into the original direction
+    Splitter(Vec<isize>),
+    // Mirror like '/', bouncing a beam going down to the left
+    DeflectLeft,
+    // Mirror like '\', bouncing a beam going down to the right
+    DeflectRight,
+    // Stops the beam
+    Absorber,
+    // Sends every incoming beam into the same direction
+    Merge(Direction),
+}
+
+impl From<char> for Cell {
+    fn from(value: char) -> Self {
+        match value {
+            '.' => Self::Empty,
+            'S' => Self::Source,
+            '^' => Self::Splitter(vec![-1, 1]),
+            // digits are splitters with a wider spread
+            '1'..='9' => {
+                let offset = value.to_digit(10).unwrap() as isize;
+                Self::Splitter(vec![-offset, offset])
+            }
+            '/' => Self::DeflectLeft,
+            '\\' => Self::DeflectRight,
+            '#' => Self::Absorber,
+            'A' => Self::Merge(Direction::Up),
+            'v' => Self::Merge(Direction::Down),
+            '<' => Self::Merge(Direction::Left),
+            '>' => Self::Merge(Direction::Right),
+            _ => panic!("Unsupported cell {value:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Manifold {
+    cells: Vec<Vec<Cell>>,
+    // width of the widest row, shorter rows are padded with empty cells
+    cols: usize,
+    // (row, col) of the beam source
+    source: (usize, usize),
+}
+
+impl From<&str> for Manifold {
+    fn from(value: &str) -> Self {
+        let cells = value
+            .trim()
+            .split("\n")
+            .map(|r| r.trim().chars().map(Cell::from).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let cols = cells.iter().map(|r| r.len()).max().unwrap();
+        let source = cells
+            .iter()
+            .enumerate()
+            .find_map(|(i, r)| r.iter().position(|c| *c == Cell::Source).map(|j| (i, j)))
+            .expect("Missing beam source");
+        Self {
+            cells,
+            cols,
+            source,
+        }
+    }
+}
+
+// Number type used to count the timelines
+// The count roughly doubles with every row of splitters,
+// so deep manifolds need wide, arbitrary precision or modular counters
+trait Counter: Clone {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn plus(&self, other: &Self) -> Self;
+}
+
+impl Counter for u64 {
+    fn zero() -> Self {
+        0
+    }
+
+    fn one() -> Self {
+        1
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        self.checked_add(*other).expect("Timeline count overflow")
+    }
+}
+
+impl Counter for u128 {
+    fn zero() -> Self {
+        0
+    }
+
+    fn one() -> Self {
+        1
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        self.checked_add(*other).expect("Timeline count overflow")
+    }
+}
+
+impl Counter for BigUint {
+    fn zero() -> Self {
+        BigUint::ZERO
+    }
+
+    fn one() -> Self {
+        BigUint::from(1u8)
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        self + other
+    }
+}
+
+// Counts modulo the prime P
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Modular<const P: u64>(u64);
+
+impl<const P: u64> Counter for Modular<P> {
+    fn zero() -> Self {
+        Self(0)
+    }
+
+    fn one() -> Self {
+        Self(1 % P)
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        Self(((self.0 as u128 + other.0 as u128) % P as u128) as u64)
+    }
+}
+
+// Prime for counting the timelines with `--counter mod`
+const MODULUS: u64 = 1_000_000_007;
+
+impl<const P: u64> Display for Modular<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (mod {P})", self.0)
+    }
+}
+
+// Result of sending the beam through the manifold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Beams<T> {
+    // number of splitters hit by a beam
+    splits: u32,
+    // number of possible paths of a single particle
+    timelines: T,
+}
+
+// The beam runs in circles through these cells
+#[derive(Debug, Clone)]
+struct BeamLoop(Vec<(usize, usize)>);
+
+impl Display for BeamLoop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Beam loop through cells")?;
+        for (row, col) in &self.0 {
+            write!(f, " ({row}, {col})")?;
+        }
+        Ok(())
+    }
+}
+
+// A beam entering a cell in a direction
+// States are numbered by cell and direction to allow plain vectors for bookkeeping
+type State = usize;
+
+impl Manifold {
+    fn state(&self, row: usize, col: usize, dir: Direction) -> State {
+        (row * self.cols + col) * 4 + dir.index()
+    }
+
+    fn position(&self, state: State) -> (usize, usize) {
+        (state / 4 / self.cols, state / 4 % self.cols)
+    }
+
+    fn cell(&self, row: usize, col: usize) -> &Cell {
+        self.cells[row].get(col).unwrap_or(&Cell::Empty)
+    }
+
+    // Step from a cell by the given offset
+    // None when the beam leaves the manifold
+    fn step(&self, (row, col): (usize, usize), (dr, dc): (isize, isize)) -> Option<(usize, usize)> {
+        let row = row
+            .checked_add_signed(dr)
+            .filter(|r| *r < self.cells.len())?;
+        let col = col.checked_add_signed(dc).filter(|c| *c < self.cols)?;
+        Some((row, col))
+    }
+
+    // The states reached by the beam leaving the cell of the given state
+    // None stands for a beam leaving the manifold through the bottom edge,
+    // beams leaving through the top or the sides are dropped
+    fn successors(&self, state: State) -> Vec<Option<State>> {
+        let (row, col) = self.position(state);
+        let dir = Direction::ALL[state % 4];
+        let (dr, dc) = dir.delta();
+        let emit = |offset: (isize, isize), dir: Direction| match self.step((row, col), offset) {
+            Some((r, c)) => Some(Some(self.state(r, c, dir))),
+            None if self.exits_bottom((row, col), offset) => Some(None),
+            None => None,
+        };
+        let beams = match self.cell(row, col) {
+            Cell::Empty | Cell::Source => vec![emit((dr, dc), dir)],
+            Cell::Splitter(offsets) => offsets
+                .iter()
+                .map(|k| emit((dr + k * dc, dc + k * dr), dir))
+                .collect(),
+            Cell::DeflectLeft => {
+                let dir = Direction::from_delta((-dc, -dr));
+                vec![emit(dir.delta(), dir)]
+            }
+            Cell::DeflectRight => {
+                let dir = Direction::from_delta((dc, dr));
+                vec![emit(dir.delta(), dir)]
+            }
+            Cell::Absorber => vec![],
+            Cell::Merge(dir) => vec![emit(dir.delta(), *dir)],
+        };
+        beams.into_iter().flatten().collect()
+    }
+
+    // Whether the step leaves the manifold below the last row within its columns
+    fn exits_bottom(&self, (row, col): (usize, usize), (dr, dc): (isize, isize)) -> bool {
+        row.checked_add_signed(dr) == Some(self.cells.len())
+            && col.checked_add_signed(dc).is_some_and(|c| c < self.cols)
+    }
+
+    // Follow the beam from the source going down through all reachable states
+    // A timeline is a beam leaving the manifold through the bottom edge, like in the puzzle.
+    // Beams leaving through the top or the sides and absorbed beams end no timeline
+    // The number of timelines reaching the end from a state is the sum over its successors,
+    // so the states are evaluated in reverse topological order
+    // A cycle would allow infinitely many timelines and is reported instead
+    fn trace<T: Counter>(&self) -> Result<Beams<T>, BeamLoop> {
+        // a source in the last row sends its beam straight out of the bottom
+        let start = self.step(self.source, Direction::Down.delta());
+        let start = start.map(|(r, c)| self.state(r, c, Direction::Down));
+
+        // iterative depth first search collecting the states in post order
+        // 0: unvisited, 1: on the current path, 2: finished
+        let mut mark = vec![0u8; self.cells.len() * self.cols * 4];
+        let mut order = Vec::new();
+        let mut stack: Vec<(State, Vec<Option<State>>)> = Vec::new();
+        if let Some(s) = start {
+            mark[s] = 1;
+            stack.push((s, self.successors(s)));
+        }
+        while let Some((state, successors)) = stack.last_mut() {
+            match successors.pop() {
+                Some(Some(next)) if mark[next] == 0 => {
+                    mark[next] = 1;
+                    let successors = self.successors(next);
+                    stack.push((next, successors));
+                }
+                Some(Some(next)) if mark[next] == 1 => {
+                    // the path from next to the current state closes the loop
+                    let i = stack.iter().position(|(s, _)| *s == next).unwrap();
+                    let cells = stack[i..].iter().map(|(s, _)| self.position(*s));
+                    return Err(BeamLoop(cells.collect()));
                 }
-                _ => (),
-            };
+                Some(_) => (),
+                None => {
+                    mark[*state] = 2;
+                    order.push(*state);
+                    stack.pop();
+                }
+            }
         }
+
+        let mut timelines = vec![T::zero(); mark.len()];
+        for state in &order {
+            timelines[*state] = self
+                .successors(*state)
+                .iter()
+                .fold(T::zero(), |sum, s| match s {
+                    Some(s) => sum.plus(&timelines[*s]),
+                    None => sum.plus(&T::one()),
+                });
+        }
+
+        let splits = order
+            .iter()
+            .map(|s| self.position(*s))
+            .filter(|(r, c)| matches!(self.cell(*r, *c), Cell::Splitter(_)))
+            .unique()
+            .count() as u32;
+
+        Ok(Beams {
+            splits,
+            timelines: start.map_or(T::one(), |s| timelines[s].clone()),
+        })
     }
+}
+
+fn get_data() -> Manifold {
+    let data_str = get_input(7).unwrap();
+    // let data_str = TEST_DATA;
+
+    Manifold::from(data_str.trim())
+}
+
+// Number of timelines of the manifold
+fn timelines<T: Counter>(manifold: &Manifold) -> T {
+    manifold
+        .trace::<T>()
+        .unwrap_or_else(|e| panic!("{e}"))
+        .timelines
+}
 
-    // for row in data {
-    //     println!("{row}");
-    // }
+pub fn part_1() {
+    let manifold = get_data();
+
+    let count = manifold
+        .trace::<u64>()
+        .unwrap_or_else(|e| panic!("{e}"))
+        .splits;
 
     assert_eq!(count, 1600);
     println!("Part 1: {count:?}");
 }
 
 pub fn part_2() {
-    let mut data = get_data();
-
-    let rows = data.len();
-    let cols = data[0].len();
-
-    // Again draw the tree like in part 1
-    for i in 1..rows {
-        for j in 0..cols {
-            let above = data[i - 1].chars().nth(j).unwrap();
-            let current = data[i].chars().nth(j).unwrap();
-            match (current, above) {
-                ('.', 'S') | ('.', '|') => data[i].replace_range(j..j + 1, "|"),
-                ('^', '|') => data[i].replace_range(j - 1..j + 2, "|^|"),
-                _ => (),
-            };
+    let manifold = get_data();
+
+    // the timelines are counted in the same pass as the splits
+    // u128 leaves plenty of room for deeper manifolds,
+    // `--counter big|mod` counts with BigUint or modulo a prime for even more splitters
+    let expected = 8632253783011u64;
+    match get_arg("--counter").as_deref() {
+        None | Some("u128") => {
+            let count = timelines::<u128>(&manifold);
+            assert_eq!(count, expected as u128);
+            println!("Part 2: {count}");
+        }
+        Some("big") => {
+            let count = timelines::<BigUint>(&manifold);
+            assert_eq!(count, BigUint::from(expected));
+            println!("Part 2: {count}");
+        }
+        Some("mod") => {
+            let count = timelines::<Modular<MODULUS>>(&manifold);
+            assert_eq!(count, Modular(expected % MODULUS));
+            println!("Part 2: {count}");
         }
+        Some(c) => panic!("Unsupported counter {c:?}"),
     }
+}
 
-    // for row in &data {
-    //     println!("{row}");
-    // }
-
-    // for every cell, calculate the number of options to reach the cell with the beam
-    // This is zero by default for every cell at start
-    // the sum of the last row is then the number of possible timelines
-    let mut tree = Vec::new();
-    let mut first_row = vec![0; cols];
-    first_row[data[0].find('S').unwrap()] = 1;
-    tree.push(first_row);
-
-    // iterate over the drawn tree and calculate for every beam the number of options
-    // to reach it
-    for (j, row) in data.iter().skip(1).enumerate() {
-        let mut tree_row = vec![0; cols];
-        for (i, char) in row.chars().enumerate() {
-            // the number of options always propagate downwards
-            // if next to the beam is a splitter, these timeline could also create the beam
-            if char == '|' {
-                // Ray from above
-                if row.chars().nth(i).unwrap() == '|' {
-                    let timelines = tree[j].get(i).unwrap();
-                    tree_row[i] = *timelines;
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                // Splitter on the left
-                if let Some(left_char) = row.chars().nth(i - 1)
-                    && left_char == '^'
-                {
-                    let timelines = tree[j].get(i - 1).unwrap();
-                    tree_row[i] += timelines;
-                }
+    fn timelines(data: &str) -> u64 {
+        Manifold::from(data.trim())
+            .trace::<u64>()
+            .unwrap()
+            .timelines
+    }
 
-                // Splitter on the right
-                if let Some(right_char) = row.chars().nth(i + 1)
-                    && right_char == '^'
-                {
-                    let timelines = tree[j].get(i + 1).unwrap();
-                    tree_row[i] += timelines;
-                }
-            }
+    #[test]
+    fn test_data() {
+        let beams = Manifold::from(TEST_DATA.trim()).trace::<u64>().unwrap();
+        assert_eq!((beams.splits, beams.timelines), (21, 40));
+    }
+
+    #[test]
+    fn beams_leaving_through_the_side_end_no_timeline() {
+        assert_eq!(timelines("S..\n^..\n..."), 1);
+        assert_eq!(timelines("..S\n..^\n..."), 1);
+        assert_eq!(timelines(".S.\n.^.\n..."), 2);
+    }
+
+    #[test]
+    fn absorbed_beams_end_no_timeline() {
+        assert_eq!(timelines(".S.\n.^.\n#.."), 1);
+        assert_eq!(timelines("S\n#"), 0);
+    }
+
+    #[test]
+    fn beams_leaving_through_the_bottom_are_timelines() {
+        assert_eq!(timelines("S"), 1);
+        assert_eq!(timelines(".S.\n.^."), 2);
+    }
+
+    #[test]
+    fn mirrors() {
+        // both mirrors turn the beam aside and back down past the absorber
+        assert_eq!(timelines(".S\n//\n.#"), 1);
+        assert_eq!(timelines("S.\n\\\\\n#."), 1);
+        // turned up and out through the top
+        assert_eq!(timelines(".S\n\\/\n.."), 0);
+    }
+
+    #[test]
+    fn merges_in_all_four_directions() {
+        // right, up, left and down again around the absorbers
+        assert_eq!(timelines("Sv<\n>.A\n#.#"), 1);
+        assert_eq!(timelines("S\nA\n."), 0);
+    }
+
+    #[test]
+    fn splitters_hit_from_the_side() {
+        // a beam going right is split up and down and keeps going right
+        let beams = Manifold::from("S..v\n>^..\n...v").trace::<u64>().unwrap();
+        assert_eq!((beams.splits, beams.timelines), (1, 2));
+    }
+
+    #[test]
+    fn wide_splitters() {
+        assert_eq!(timelines("..S..\n..2..\n.#.#."), 2);
+        assert_eq!(timelines("..S..\n..1..\n.#.#."), 0);
+        // both beams leave through the sides
+        assert_eq!(timelines("..S..\n..3..\n....."), 0);
+    }
+
+    #[test]
+    fn loops_are_reported() {
+        let data = "S..\n\\.v\n>./\n...";
+        let BeamLoop(cells) = Manifold::from(data).trace::<u64>().unwrap_err();
+        assert_eq!(cells, vec![(2, 2), (2, 1), (2, 0), (2, 1), (2, 2), (1, 2)]);
+    }
+
+    // Full triangle of splitters, every level doubles the number of timelines
+    fn triangle(levels: usize) -> Manifold {
+        let cols = 2 * levels + 1;
+        let mut rows = vec![".".repeat(levels) + "S" + &".".repeat(levels)];
+        for level in 0..levels {
+            rows.push(".".repeat(cols));
+            let row = (0..cols)
+                .map(|c| {
+                    let offset = c.abs_diff(levels);
+                    if offset <= level && offset % 2 == level % 2 {
+                        '^'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            rows.push(row);
         }
-        tree.push(tree_row);
+        rows.push(".".repeat(cols));
+        Manifold::from(rows.join("\n").as_str())
     }
 
-    // for row in &tree {
-    //     println!("{row:?}");
-    // }
+    #[test]
+    fn deep_manifolds() {
+        let manifold = triangle(130);
+        let big = manifold.trace::<BigUint>().unwrap();
+        assert_eq!(big.splits, 130 * 131 / 2);
+        assert_eq!(big.timelines, BigUint::from(1u8) << 130);
 
-    let tree_leaves = tree.last().unwrap();
-    // println!("{:?}", tree_leaves);
+        let modular = super::timelines::<Modular<MODULUS>>(&manifold);
+        assert_eq!(BigUint::from(modular.0), big.timelines % MODULUS);
 
-    let count: i64 = tree_leaves.iter().sum();
+        // wide counters agree while they do not overflow
+        let manifold = triangle(100);
+        let big = super::timelines::<BigUint>(&manifold);
+        assert_eq!(BigUint::from(super::timelines::<u128>(&manifold)), big);
+    }
+
+    #[test]
+    #[should_panic(expected = "Timeline count overflow")]
+    fn u128_overflow() {
+        super::timelines::<u128>(&triangle(130));
+    }
 
-    assert_eq!(count, 8632253783011);
-    println!("Part 2: {count}");
+    #[test]
+    #[should_panic(expected = "Timeline count overflow")]
+    fn u64_overflow() {
+        super::timelines::<u64>(&triangle(70));
+    }
 }
@@ -1,7 +1,16 @@
 use itertools::Itertools;
-use std::{collections::HashMap, hash::Hash};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+    fs,
+    ops::Range,
+    str::FromStr,
+};
 
-use crate::utils::get_input;
+use crate::{
+    disjoint_set::DisjointSet,
+    utils::{get_arg, get_input},
+};
 
 #[allow(dead_code)]
 const TEST_DATA: &str = r#"
@@ -53,123 +62,708 @@ impl From<&str> for Point {
 }
 
 impl Point {
-    fn dist(&self, other: &Self) -> i64 {
-        (other.x - self.x).pow(2) + (other.y - self.y).pow(2) + (other.z - self.z).pow(2)
+    fn coord(&self, axis: usize) -> i64 {
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
     }
 }
 
-pub fn part_1() {
-    let data = get_data();
+// Distance used to find the closest junction boxes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    SquaredEuclidean,
+    Manhattan,
+    Chebyshev,
+    // squared euclidean with a weight per axis
+    // The weights must not be negative, otherwise the distance along a single axis
+    // is no lower bound for the k-d tree search anymore
+    Weighted([i64; 3]),
+}
 
-    let mut circuit_id: u32 = 0;
-    let mut circuits: HashMap<u32, Vec<Point>> = HashMap::new();
-    let mut points: HashMap<Point, u32> = HashMap::new();
-
-    // generate all possible combinations
-    // calculate and sort by each distance
-    // take the first 1000 closest points
-    // build the circuits
-    data.iter()
-        .tuple_combinations()
-        .map(|(a, b)| (a, b, a.dist(b)))
-        .sorted_unstable_by(|a, b| a.2.cmp(&b.2))
-        .take(1000)
-        .for_each(
-            |(p1, p2, _)| match (points.get(p1).cloned(), points.get(p2).cloned()) {
-                // None of the two points is in a circuit
-                // create a new circuit
-                (None, None) => {
-                    circuits.insert(circuit_id, vec![p1.clone(), p2.clone()]);
-                    points.insert(p1.clone(), circuit_id);
-                    points.insert(p2.clone(), circuit_id);
-                    circuit_id += 1;
-                }
-                // the right point is in a circuit, add the left
-                (None, Some(i)) => {
-                    circuits.entry(i).and_modify(|v| v.push(p1.clone()));
-                    points.insert(p1.clone(), i);
-                }
-                // the left point is in a circuit, add the right
-                (Some(i), None) => {
-                    circuits.entry(i).and_modify(|v| v.push(p2.clone()));
-                    points.insert(p2.clone(), i);
+impl FromStr for Metric {
+    type Err = String;
+
+    // `euclidean`, `manhattan`, `chebyshev` or `weighted:x,y,z`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "euclidean" => Ok(Self::SquaredEuclidean),
+            "manhattan" => Ok(Self::Manhattan),
+            "chebyshev" => Ok(Self::Chebyshev),
+            _ => {
+                let weights = value
+                    .strip_prefix("weighted:")
+                    .and_then(|w| {
+                        w.split(',')
+                            .map(|x| x.parse::<i64>().ok())
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .and_then(|w| <[i64; 3]>::try_from(w).ok())
+                    .ok_or(format!("Unsupported metric {value:?}"))?;
+                if weights.iter().any(|w| *w < 0) {
+                    return Err(format!("Negative weight in metric {value:?}"));
                 }
-                // both points are in a circuit
-                // when in different circuits join them
-                (Some(i), Some(j)) => {
-                    if i != j {
-                        let o = circuits.remove(&j).unwrap();
-                        points.extend(o.iter().map(|p| (p.clone(), i)));
-                        circuits.entry(i).and_modify(|v| v.extend(o));
-                    }
+                Ok(Self::Weighted(weights))
+            }
+        }
+    }
+}
+
+impl Metric {
+    fn dist(&self, a: &Point, b: &Point) -> i64 {
+        (0..3)
+            .map(|axis| self.axis_dist(axis, b.coord(axis) - a.coord(axis)))
+            .fold(0, |acc, d| match self {
+                Self::Chebyshev => acc.max(d),
+                _ => acc + d,
+            })
+    }
+
+    // Distance between two points differing only in one axis
+    // This is a lower bound for all points on the other side of a splitting plane
+    fn axis_dist(&self, axis: usize, diff: i64) -> i64 {
+        match self {
+            Self::SquaredEuclidean => diff * diff,
+            Self::Manhattan | Self::Chebyshev => diff.abs(),
+            Self::Weighted(w) => w[axis] * diff * diff,
+        }
+    }
+}
+
+// Order of pairs with equal distance
+// Pairs are compared by their first and then by their second point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TieBreak {
+    // position of the points in the input
+    InputOrder,
+    // coordinates of the points, independent of the input order
+    Coordinates,
+}
+
+impl FromStr for TieBreak {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "input" => Ok(Self::InputOrder),
+            "coordinates" => Ok(Self::Coordinates),
+            _ => Err(format!("Unsupported tie break {value:?}")),
+        }
+    }
+}
+
+// Metric and tie break from `--metric` and `--tie-break`
+// Defaults to the puzzle rules, squared euclidean distances in input order
+fn pairing_args() -> (Metric, TieBreak) {
+    let metric = get_arg("--metric").map_or(Ok(Metric::SquaredEuclidean), |m| m.parse());
+    let tie_break = get_arg("--tie-break").map_or(Ok(TieBreak::InputOrder), |t| t.parse());
+    (
+        metric.unwrap_or_else(|e| panic!("{e}")),
+        tie_break.unwrap_or_else(|e| panic!("{e}")),
+    )
+}
+
+// Implicit k-d tree over the point indices
+// Every sub slice of order stores its splitting point at the middle,
+// smaller coordinates of the splitting axis left and larger ones right
+struct KdTree {
+    points: Vec<Point>,
+    metric: Metric,
+    order: Vec<usize>,
+}
+
+impl KdTree {
+    fn new(points: Vec<Point>, metric: Metric) -> Self {
+        let mut order = (0..points.len()).collect::<Vec<_>>();
+        Self::build(&points, &mut order, 0);
+        Self {
+            points,
+            metric,
+            order,
+        }
+    }
+
+    fn build(points: &[Point], order: &mut [usize], depth: usize) {
+        if order.len() <= 1 {
+            return;
+        }
+        let mid = order.len() / 2;
+        order.select_nth_unstable_by_key(mid, |i| points[*i].coord(depth % 3));
+        let (left, right) = order.split_at_mut(mid);
+        Self::build(points, left, depth + 1);
+        Self::build(points, &mut right[1..], depth + 1);
+    }
+
+    // The k nearest neighbours of a point as (distance, index)
+    // sorted by distance, ties by index
+    fn nearest(&self, query: usize, k: usize) -> Vec<(i64, usize)> {
+        let mut best = BinaryHeap::with_capacity(k + 1);
+        self.search(0..self.order.len(), 0, query, k, &mut best);
+        best.into_sorted_vec()
+    }
+
+    fn search(
+        &self,
+        range: Range<usize>,
+        depth: usize,
+        query: usize,
+        k: usize,
+        best: &mut BinaryHeap<(i64, usize)>,
+    ) {
+        if range.is_empty() {
+            return;
+        }
+        let mid = (range.start + range.end) / 2;
+        let (q, p) = (&self.points[query], &self.points[self.order[mid]]);
+
+        // best is a max heap, holding the worst candidate on top
+        if self.order[mid] != query {
+            best.push((self.metric.dist(q, p), self.order[mid]));
+            if best.len() > k {
+                best.pop();
+            }
+        }
+
+        let axis = depth % 3;
+        let diff = q.coord(axis) - p.coord(axis);
+        let (near, far) = if diff < 0 {
+            (range.start..mid, mid + 1..range.end)
+        } else {
+            (mid + 1..range.end, range.start..mid)
+        };
+        self.search(near, depth + 1, query, k, best);
+        // points on the far side are at least the distance to the splitting plane away
+        if best.len() < k || self.metric.axis_dist(axis, diff) <= best.peek().unwrap().0 {
+            self.search(far, depth + 1, query, k, best);
+        }
+    }
+}
+
+// Lazily yields all pairs of points as (a, b, distance) with a < b
+// sorted by distance, ties by the tie break rule
+// Internally the points are relabeled in tie break order,
+// so ties can always be resolved by comparing the labels.
+// Every point keeps a sorted list of its nearest neighbours,
+// which is refetched at double the size once used up.
+// A heap holds the next unused neighbour of every point,
+// so its top is always the globally closest remaining pair
+struct ClosestPairs {
+    tree: KdTree,
+    // input index of every label
+    index: Vec<usize>,
+    neighbours: Vec<Vec<(i64, usize)>>,
+    next: Vec<usize>,
+    heap: BinaryHeap<Reverse<(i64, usize, usize)>>,
+}
+
+impl ClosestPairs {
+    fn new(points: &[Point], metric: Metric, tie_break: TieBreak) -> Self {
+        let mut index = (0..points.len()).collect::<Vec<_>>();
+        if tie_break == TieBreak::Coordinates {
+            index.sort_by_key(|i| (points[*i].x, points[*i].y, points[*i].z));
+        }
+        let tree = KdTree::new(index.iter().map(|i| points[*i].clone()).collect(), metric);
+        let neighbours = (0..points.len())
+            .map(|i| tree.nearest(i, 4))
+            .collect::<Vec<_>>();
+        let heap = neighbours
+            .iter()
+            .enumerate()
+            .filter_map(|(i, n)| n.first().map(|(d, j)| Reverse((*d, i, *j))))
+            .collect();
+        Self {
+            tree,
+            index,
+            neighbours,
+            next: vec![0; points.len()],
+            heap,
+        }
+    }
+
+    // Move on to the next neighbour of point i
+    fn advance(&mut self, i: usize) {
+        self.next[i] += 1;
+        let fetched = self.neighbours[i].len();
+        if self.next[i] == fetched && fetched < self.tree.points.len() - 1 {
+            self.neighbours[i] = self.tree.nearest(i, fetched * 2);
+        }
+        if let Some((d, j)) = self.neighbours[i].get(self.next[i]) {
+            self.heap.push(Reverse((*d, i, *j)));
+        }
+    }
+}
+
+impl Iterator for ClosestPairs {
+    type Item = (usize, usize, i64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Reverse((d, i, j)) = self.heap.pop()?;
+            self.advance(i);
+            // every pair is found from both sides, only keep one
+            if i < j {
+                let (a, b) = (self.index[i], self.index[j]);
+                return Some((a.min(b), a.max(b), d));
+            }
+        }
+    }
+}
+
+// A connection between the points with index a and b
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Edge {
+    a: usize,
+    b: usize,
+    weight: i64,
+    // position among all pairs of points sorted by distance
+    rank: usize,
+}
+
+#[derive(Debug, Clone)]
+struct SpanningTree {
+    // number of points
+    size: usize,
+    // accepted edges in the order they were added
+    edges: Vec<Edge>,
+}
+
+impl SpanningTree {
+    // Kruskal's algorithm
+    // Walk all pairs by increasing distance and accept every pair,
+    // which connects two different components
+    // The pairs are generated lazily, so only the pairs up to the last edge are ever looked at
+    fn new(points: &[Point], metric: Metric, tie_break: TieBreak) -> Self {
+        let mut components = DisjointSet::new(points.len());
+        let mut edges = Vec::with_capacity(points.len().saturating_sub(1));
+
+        for (rank, (a, b, weight)) in ClosestPairs::new(points, metric, tie_break).enumerate() {
+            if components.union(a, b) {
+                edges.push(Edge { a, b, weight, rank });
+                if components.components() == 1 {
+                    break;
                 }
-            },
+            }
+        }
+
+        Self {
+            size: points.len(),
+            edges,
+        }
+    }
+
+    // Sum of all edge weights, minimal among all spanning trees
+    // The parts only need the edges, the tests check the tree with it
+    #[cfg(test)]
+    fn total_weight(&self) -> i64 {
+        self.edges.iter().map(|e| e.weight).sum()
+    }
+
+    // The edge joining the last two components
+    fn last_edge(&self) -> Option<&Edge> {
+        self.edges.last()
+    }
+
+    // The circuits after connecting the given number of closest pairs
+    // Pairs not in the tree would have closed a loop and don't change the circuits
+    fn circuits(&self, pairs: usize) -> DisjointSet {
+        let mut circuits = DisjointSet::new(self.size);
+        for e in self.edges.iter().take_while(|e| e.rank < pairs) {
+            circuits.union(e.a, e.b);
+        }
+        circuits
+    }
+}
+
+// A group of connected junction boxes
+#[derive(Debug, Clone)]
+struct Circuit {
+    // indices of the points in the circuit
+    members: Vec<usize>,
+    // corners of the bounding box
+    min: Point,
+    max: Point,
+    centroid: [f64; 3],
+}
+
+impl Circuit {
+    fn new(points: &[Point], members: Vec<usize>) -> Self {
+        let bound = |f: fn(i64, i64) -> i64| {
+            let mut p = points[members[0]].clone();
+            for m in &members {
+                p.x = f(p.x, points[*m].x);
+                p.y = f(p.y, points[*m].y);
+                p.z = f(p.z, points[*m].z);
+            }
+            p
+        };
+        let centroid = [0, 1, 2].map(|axis| {
+            members
+                .iter()
+                .map(|m| points[*m].coord(axis) as f64)
+                .sum::<f64>()
+                / members.len() as f64
+        });
+        Self {
+            min: bound(i64::min),
+            max: bound(i64::max),
+            centroid,
+            members,
+        }
+    }
+
+    // Every point is part of exactly one circuit,
+    // unconnected points form circuits of size one
+    // Sorted from the largest to the smallest circuit
+    fn collect(points: &[Point], circuits: &mut DisjointSet) -> Vec<Self> {
+        circuits
+            .groups()
+            .into_iter()
+            .map(|members| Self::new(points, members))
+            .sorted_by_key(|c| Reverse(c.members.len()))
+            .collect()
+    }
+}
+
+// Number of circuits by size
+fn histogram(circuits: &[Circuit]) -> BTreeMap<usize, usize> {
+    circuits
+        .iter()
+        .map(|c| c.members.len())
+        .counts()
+        .into_iter()
+        .collect()
+}
+
+fn to_json(points: &[Point], circuits: &[Circuit]) -> String {
+    let coords = |p: &Point| format!("[{},{},{}]", p.x, p.y, p.z);
+    let histogram = histogram(circuits)
+        .iter()
+        .map(|(size, count)| format!("\"{size}\":{count}"))
+        .join(",");
+    let circuits = circuits
+        .iter()
+        .enumerate()
+        .map(|(id, c)| {
+            let members = c
+                .members
+                .iter()
+                .map(|m| format!("{{\"index\":{m},\"point\":{}}}", coords(&points[*m])))
+                .join(",");
+            format!(
+                "{{\"id\":{id},\"size\":{},\"members\":[{members}],\"bbox\":{{\"min\":{},\"max\":{}}},\"centroid\":[{},{},{}]}}",
+                c.members.len(),
+                coords(&c.min),
+                coords(&c.max),
+                c.centroid[0],
+                c.centroid[1],
+                c.centroid[2],
+            )
+        })
+        .join(",\n    ");
+    format!("{{\n  \"circuits\": [\n    {circuits}\n  ],\n  \"histogram\": {{{histogram}}}\n}}\n")
+}
+
+// One row per circuit, the members are separated by spaces
+fn to_csv(circuits: &[Circuit]) -> String {
+    let mut csv = String::from(
+        "id,size,min_x,min_y,min_z,max_x,max_y,max_z,centroid_x,centroid_y,centroid_z,members\n",
+    );
+    for (id, c) in circuits.iter().enumerate() {
+        let (min, max, centroid) = (&c.min, &c.max, &c.centroid);
+        csv += &format!(
+            "{id},{},{},{},{},{},{},{},{},{},{},{}\n",
+            c.members.len(),
+            min.x,
+            min.y,
+            min.z,
+            max.x,
+            max.y,
+            max.z,
+            centroid[0],
+            centroid[1],
+            centroid[2],
+            c.members.iter().join(" ")
         );
+    }
+    csv
+}
+
+// Write all circuits to a file when requested with `--export json` or `--export csv`
+// and print the size histogram
+fn export(points: &[Point], circuits: &[Circuit]) {
+    let Some(format) = get_arg("--export") else {
+        return;
+    };
+    let content = match format.as_str() {
+        "json" => to_json(points, circuits),
+        "csv" => to_csv(circuits),
+        _ => panic!("Unsupported export format {format:?}"),
+    };
+    let path = format!("day8_circuits.{format}");
+    fs::write(&path, content).unwrap();
+    println!("Exported {} circuits to {path}", circuits.len());
+    for (size, count) in histogram(circuits) {
+        println!("  size {size:>4}: {count}");
+    }
+}
+
+pub fn part_1() {
+    let data = get_data();
+
+    let (metric, tie_break) = pairing_args();
+
+    // connect the 1000 closest pairs of points
+    let mut components = SpanningTree::new(&data, metric, tie_break).circuits(1000);
+    let circuits = Circuit::collect(&data, &mut components);
+    export(&data, &circuits);
 
     // take the three longest circuits
     // and calculate the product of their lengths
-    let prod: usize = circuits
-        .values()
-        .map(|c| c.len())
-        .sorted_unstable()
-        .rev()
-        .take(3)
-        .product();
-
-    assert_eq!(prod, 97384);
+    let prod: usize = circuits.iter().take(3).map(|c| c.members.len()).product();
+
+    if (metric, tie_break) == (Metric::SquaredEuclidean, TieBreak::InputOrder) {
+        assert_eq!(prod, 97384);
+    }
     println!("Part 1: {prod:?}");
 }
 
 pub fn part_2() {
     let data = get_data();
 
-    // Same as in part 1
-    // But dont stop at 1000
-    // break when all points are in one circuit
-    let iter = data
-        .iter()
-        .tuple_combinations()
-        .map(|(a, b)| (a, b, a.dist(b)))
-        .sorted_unstable_by(|a, b| a.2.cmp(&b.2));
-
-    let mut circuit_id: u32 = 0;
-    let mut circuits: HashMap<u32, Vec<Point>> = HashMap::new();
-    let mut points: HashMap<Point, u32> = HashMap::new();
-    let mut prod = 0;
-
-    for (p1, p2, _) in iter {
-        match (points.get(p1).cloned(), points.get(p2).cloned()) {
-            (None, None) => {
-                circuits.insert(circuit_id, vec![p1.clone(), p2.clone()]);
-                points.insert(p1.clone(), circuit_id);
-                points.insert(p2.clone(), circuit_id);
-                circuit_id += 1;
-            }
-            (None, Some(i)) => {
-                circuits.entry(i).and_modify(|v| v.push(p1.clone()));
-                points.insert(p1.clone(), i);
+    // The last edge of the spanning tree connects all points into one circuit
+    let (metric, tie_break) = pairing_args();
+    let tree = SpanningTree::new(&data, metric, tie_break);
+    let last = tree.last_edge().unwrap();
+    let prod = data[last.a].x * data[last.b].x;
+
+    if (metric, tie_break) == (Metric::SquaredEuclidean, TieBreak::InputOrder) {
+        assert_eq!(prod, 9003685096);
+    }
+    println!("Part 2: {prod}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_points() -> Vec<Point> {
+        TEST_DATA.trim().split("\n").map(Point::from).collect()
+    }
+
+    // Prim's algorithm over all pairs
+    fn minimum_weight(points: &[Point], metric: Metric) -> i64 {
+        let mut dist = vec![i64::MAX; points.len()];
+        let mut done = vec![false; points.len()];
+        dist[0] = 0;
+        let mut total = 0;
+        for _ in 0..points.len() {
+            let i = (0..points.len())
+                .filter(|i| !done[*i])
+                .min_by_key(|i| dist[*i])
+                .unwrap();
+            done[i] = true;
+            total += dist[i];
+            for j in 0..points.len() {
+                dist[j] = dist[j].min(metric.dist(&points[i], &points[j]));
             }
-            (Some(i), None) => {
-                circuits.entry(i).and_modify(|v| v.push(p2.clone()));
-                points.insert(p2.clone(), i);
+        }
+        total
+    }
+
+    #[test]
+    fn spanning_tree() {
+        let points = test_points();
+        let tree = SpanningTree::new(&points, Metric::SquaredEuclidean, TieBreak::InputOrder);
+        assert_eq!(tree.edges.len(), points.len() - 1);
+        assert_eq!(
+            tree.total_weight(),
+            minimum_weight(&points, Metric::SquaredEuclidean)
+        );
+
+        let last = tree.last_edge().unwrap();
+        assert_eq!(points[last.a].x * points[last.b].x, 25272);
+
+        let mut circuits = tree.circuits(10);
+        assert_eq!(circuits.components(), 11);
+        let sizes = Circuit::collect(&points, &mut circuits)
+            .iter()
+            .map(|c| c.members.len())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes[..3], [5, 4, 2]);
+        assert_eq!(sizes.iter().take(3).product::<usize>(), 40);
+    }
+
+    const METRICS: [Metric; 4] = [
+        Metric::SquaredEuclidean,
+        Metric::Manhattan,
+        Metric::Chebyshev,
+        Metric::Weighted([1, 4, 0]),
+    ];
+
+    // All pairs sorted by distance and the tie break rule
+    fn sorted_pairs(
+        points: &[Point],
+        metric: Metric,
+        tie_break: TieBreak,
+    ) -> Vec<(usize, usize, i64)> {
+        let mut label = (0..points.len()).collect::<Vec<_>>();
+        if tie_break == TieBreak::Coordinates {
+            let mut index = label.clone();
+            index.sort_by_key(|i| (points[*i].x, points[*i].y, points[*i].z));
+            for (l, i) in index.iter().enumerate() {
+                label[*i] = l;
             }
-            (Some(i), Some(j)) => {
-                if i != j {
-                    let o = circuits.remove(&j).unwrap();
-                    points.extend(o.iter().map(|p| (p.clone(), i)));
-                    circuits.entry(i).and_modify(|v| v.extend(o));
+        }
+        (0..points.len())
+            .tuple_combinations()
+            .map(|(a, b)| (a, b, metric.dist(&points[a], &points[b])))
+            .sorted_by_key(|(a, b, d)| (*d, label[*a].min(label[*b]), label[*a].max(label[*b])))
+            .collect()
+    }
+
+    #[test]
+    fn closest_pairs_match_sorting_all_pairs() {
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = |m: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % m) as i64
+        };
+        for n in [0, 1, 2, 3, 10, 40] {
+            // few distinct coordinates give many ties and duplicate points
+            let points = (0..n)
+                .map(|_| Point {
+                    x: random(5),
+                    y: random(5),
+                    z: random(5) - 2,
+                })
+                .collect::<Vec<_>>();
+            for metric in METRICS {
+                for tie_break in [TieBreak::InputOrder, TieBreak::Coordinates] {
+                    assert_eq!(
+                        ClosestPairs::new(&points, metric, tie_break).collect::<Vec<_>>(),
+                        sorted_pairs(&points, metric, tie_break),
+                        "{metric:?} {tie_break:?} {points:?}"
+                    );
                 }
             }
         }
-        // Check if done
-        // calculate final product
-        if points.len() == data.len() && circuits.len() == 1 {
-            // println!("done {:?} {:?}", p1, p2);
-            prod = p1.x * p2.x;
-            break;
+
+        let points = test_points();
+        for metric in METRICS {
+            assert_eq!(
+                ClosestPairs::new(&points, metric, TieBreak::InputOrder).collect::<Vec<_>>(),
+                sorted_pairs(&points, metric, TieBreak::InputOrder)
+            );
         }
     }
 
-    assert_eq!(prod, 9003685096);
-    println!("Part 2: {prod}");
+    #[test]
+    fn metrics() {
+        let (a, b) = (Point::from("1,2,3"), Point::from("4,0,3"));
+        assert_eq!(Metric::SquaredEuclidean.dist(&a, &b), 13);
+        assert_eq!(Metric::Manhattan.dist(&a, &b), 5);
+        assert_eq!(Metric::Chebyshev.dist(&a, &b), 3);
+        assert_eq!(Metric::Weighted([2, 0, 5]).dist(&a, &b), 18);
+
+        assert_eq!("euclidean".parse(), Ok(Metric::SquaredEuclidean));
+        assert_eq!("manhattan".parse(), Ok(Metric::Manhattan));
+        assert_eq!("chebyshev".parse(), Ok(Metric::Chebyshev));
+        assert_eq!("weighted:1,0,4".parse(), Ok(Metric::Weighted([1, 0, 4])));
+        assert!("weighted:1,-1,4".parse::<Metric>().is_err());
+        assert!("weighted:1,1".parse::<Metric>().is_err());
+        assert!("taxicab".parse::<Metric>().is_err());
+    }
+
+    #[test]
+    fn tie_breaks() {
+        assert_eq!("input".parse(), Ok(TieBreak::InputOrder));
+        assert_eq!("coordinates".parse(), Ok(TieBreak::Coordinates));
+        assert!("random".parse::<TieBreak>().is_err());
+
+        // all pairs of the square have distance 1 or 2
+        let points = ["1,1,0", "0,0,0", "1,0,0", "0,1,0"].map(Point::from);
+        let pairs = |tie_break| {
+            ClosestPairs::new(&points, Metric::SquaredEuclidean, tie_break)
+                .map(|(a, b, _)| (a, b))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            pairs(TieBreak::InputOrder),
+            vec![(0, 2), (0, 3), (1, 2), (1, 3), (0, 1), (2, 3)]
+        );
+        // ordered by the points (0,0,0), (0,1,0), (1,0,0), (1,1,0)
+        assert_eq!(
+            pairs(TieBreak::Coordinates),
+            vec![(1, 3), (1, 2), (0, 3), (0, 2), (0, 1), (2, 3)]
+        );
+    }
+
+    // Circuits of three, two and two single boxes
+    fn clustering() -> (Vec<Point>, Vec<Circuit>) {
+        let points = [
+            "0,0,0", "3,0,0", "10,10,10", "0,6,3", "1,1,-1", "2,2,-2", "5,5,5",
+        ]
+        .map(Point::from)
+        .to_vec();
+        let mut components = DisjointSet::new(points.len());
+        components.union(0, 1);
+        components.union(1, 3);
+        components.union(4, 5);
+        let circuits = Circuit::collect(&points, &mut components);
+        (points, circuits)
+    }
+
+    #[test]
+    fn circuits() {
+        let (_, circuits) = clustering();
+        let members = circuits
+            .iter()
+            .map(|c| c.members.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(members, vec![vec![0, 1, 3], vec![4, 5], vec![2], vec![6]]);
+        assert_eq!(
+            (circuits[0].min.clone(), circuits[0].max.clone()),
+            (Point::from("0,0,0"), Point::from("3,6,3"))
+        );
+        assert_eq!(circuits[0].centroid, [1.0, 2.0, 1.0]);
+        assert_eq!(
+            (circuits[1].min.clone(), circuits[1].max.clone()),
+            (Point::from("1,1,-2"), Point::from("2,2,-1"))
+        );
+        assert_eq!(circuits[1].centroid, [1.5, 1.5, -1.5]);
+        assert_eq!(
+            histogram(&circuits),
+            BTreeMap::from([(1, 2), (2, 1), (3, 1)])
+        );
+    }
+
+    #[test]
+    fn exports() {
+        let (points, circuits) = clustering();
+        assert_eq!(
+            to_json(&points, &circuits),
+            r#"{
+  "circuits": [
+    {"id":0,"size":3,"members":[{"index":0,"point":[0,0,0]},{"index":1,"point":[3,0,0]},{"index":3,"point":[0,6,3]}],"bbox":{"min":[0,0,0],"max":[3,6,3]},"centroid":[1,2,1]},
+    {"id":1,"size":2,"members":[{"index":4,"point":[1,1,-1]},{"index":5,"point":[2,2,-2]}],"bbox":{"min":[1,1,-2],"max":[2,2,-1]},"centroid":[1.5,1.5,-1.5]},
+    {"id":2,"size":1,"members":[{"index":2,"point":[10,10,10]}],"bbox":{"min":[10,10,10],"max":[10,10,10]},"centroid":[10,10,10]},
+    {"id":3,"size":1,"members":[{"index":6,"point":[5,5,5]}],"bbox":{"min":[5,5,5],"max":[5,5,5]},"centroid":[5,5,5]}
+  ],
+  "histogram": {"1":2,"2":1,"3":1}
+}
+"#
+        );
+        assert_eq!(
+            to_csv(&circuits),
+            "id,size,min_x,min_y,min_z,max_x,max_y,max_z,centroid_x,centroid_y,centroid_z,members\n\
+            0,3,0,0,0,3,6,3,1,2,1,0 1 3\n\
+            1,2,1,1,-2,2,2,-1,1.5,1.5,-1.5,4 5\n\
+            2,1,10,10,10,10,10,10,10,10,10,2\n\
+            3,1,5,5,5,5,5,5,5,5,5,6\n"
+        );
+    }
 }
@@ -1,6 +1,11 @@
+use std::{cmp::Reverse, collections::BinaryHeap, fs};
+
 use itertools::Itertools;
 
-use crate::utils::get_input;
+use crate::{
+    polygon::{CompressedPolygon, Point, Polygon, Rect},
+    utils::{get_arg, get_input},
+};
 
 #[allow(dead_code)]
 const TEST_DATA: &str = r#"
@@ -14,92 +19,251 @@ const TEST_DATA: &str = r#"
 7,3
 "#;
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-struct Point {
-    x: i64,
-    y: i64,
+fn get_data() -> Vec<Point> {
+    let data_str = get_input(9).unwrap();
+    // let data_str = TEST_DATA;
+
+    data_str.trim().split("\n").map(Point::from).collect()
 }
 
-impl From<&str> for Point {
-    fn from(value: &str) -> Self {
-        let mut s = value.trim().split(',').map(|s| s.parse().unwrap());
-        Self {
-            x: s.next().unwrap(),
-            y: s.next().unwrap(),
-        }
+// Additional requirements for the rectangles
+// The aspect ratio is the width divided by the height
+#[derive(Debug, Clone, Copy, Default)]
+struct Constraints {
+    min_width: i64,
+    min_height: i64,
+    min_aspect: Option<f64>,
+    max_aspect: Option<f64>,
+    contains: Option<Point>,
+}
+
+impl Constraints {
+    fn allows(&self, rect: &Rect) -> bool {
+        let aspect = rect.width() as f64 / rect.height() as f64;
+        rect.width() >= self.min_width
+            && rect.height() >= self.min_height
+            && self.min_aspect.is_none_or(|min| aspect >= min)
+            && self.max_aspect.is_none_or(|max| aspect <= max)
+            && self.contains.is_none_or(|p| rect.contains(p))
     }
 }
 
-impl Point {
-    fn area(&self, other: &Self) -> i64 {
-        ((self.x - other.x).abs() + 1) * ((self.y - other.y).abs() + 1)
+// The k largest rectangles spanned by two of the red tiles
+// which satisfy the constraints and the additional filter
+// Returns the two corners of each rectangle sorted by decreasing area,
+// equal areas keep the order of the input
+fn top_rectangles(
+    corners: &[Point],
+    k: usize,
+    constraints: &Constraints,
+    filter: impl Fn(&Rect) -> bool,
+) -> Vec<(Point, Point)> {
+    // min heap keeping the k best rectangles, the worst one on top
+    let mut best = BinaryHeap::with_capacity(k + 1);
+    for ((i, a), (j, b)) in corners.iter().enumerate().tuple_combinations() {
+        let rect = Rect::new(*a, *b);
+        if constraints.allows(&rect) && filter(&rect) {
+            best.push(Reverse((rect.area(), Reverse((i, j)))));
+            if best.len() > k {
+                best.pop();
+            }
+        }
     }
+    best.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((_, Reverse((i, j))))| (corners[i], corners[j]))
+        .collect()
 }
 
-fn get_data() -> Vec<Point> {
-    let data_str = get_input(9).unwrap();
-    // let data_str = TEST_DATA;
+// Draw the polygon with its interior and the selected rectangle,
+// when requested with `--render svg`
+// Every tile is a unit square centred on its coordinates
+fn render(polygon: &Polygon, best: &Rect, part: u32) {
+    match get_arg("--render").as_deref() {
+        Some("svg") => (),
+        Some(format) => panic!("Unsupported render format {format:?}"),
+        None => return,
+    }
 
-    data_str.trim().split("\n").map(Point::from).collect()
+    let vertices = polygon.vertices();
+    let bbox = vertices
+        .iter()
+        .fold(Rect::new(vertices[0], vertices[0]), |r, v| {
+            Rect::new(
+                Point {
+                    x: r.min.x.min(v.x),
+                    y: r.min.y.min(v.y),
+                },
+                Point {
+                    x: r.max.x.max(v.x),
+                    y: r.max.y.max(v.y),
+                },
+            )
+        });
+    // scale the longer side to 1000 pixels
+    let (width, height) = (bbox.max.x - bbox.min.x + 1, bbox.max.y - bbox.min.y + 1);
+    let scale = 1000.0 / width.max(height) as f64;
+    let points = vertices
+        .iter()
+        .map(|v| format!("{},{}", v.x, v.y))
+        .join(" ");
+
+    let svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {width} {height}">
+  <polygon points="{points}" fill="green" stroke="red" stroke-width="2" vector-effect="non-scaling-stroke"/>
+  <rect x="{}" y="{}" width="{}" height="{}" fill="blue" fill-opacity="0.4" stroke="blue" stroke-width="2" vector-effect="non-scaling-stroke"/>
+</svg>
+"#,
+        (width as f64 * scale).ceil(),
+        (height as f64 * scale).ceil(),
+        bbox.min.x as f64 - 0.5,
+        bbox.min.y as f64 - 0.5,
+        best.min.x as f64 - 0.5,
+        best.min.y as f64 - 0.5,
+        best.max.x - best.min.x + 1,
+        best.max.y - best.min.y + 1,
+    );
+    let path = format!("day9_part{part}.svg");
+    fs::write(&path, svg).unwrap();
+    println!("Rendered part {part} to {path}");
 }
 
 pub fn part_1() {
     let data = get_data();
 
-    let area = data
-        .iter()
-        .tuple_combinations()
-        .map(|(a, b)| a.area(b))
-        .max()
-        .unwrap();
+    let (a, b) = top_rectangles(&data, 1, &Constraints::default(), |_| true)[0];
+    let best = Rect::new(a, b);
+    render(&Polygon::new(data), &best, 1);
+    let area = best.area();
 
     assert_eq!(area, 4790063600);
     println!("Part 1: {area}");
 }
 
-#[derive(Debug, Clone)]
-struct Line {
-    a: Point,
-    b: Point,
+pub fn part_2() {
+    let data = get_data();
+    let polygon = Polygon::new(data.clone());
+    let winding = polygon
+        .validate()
+        .unwrap_or_else(|e| panic!("Invalid polygon: {e}"));
+    println!("Polygon: {} vertices, {winding}", data.len());
+    // rasterise the polygon once, so every rectangle can be checked in constant time
+    let compressed = CompressedPolygon::new(&polygon);
+
+    // Check for every rectangle, whether it lies completely inside the polygon
+    // Calculate the area as above and select the largest
+    let (a, b) = top_rectangles(&data, 1, &Constraints::default(), |r| {
+        compressed.contains_rect(r)
+    })[0];
+    let best = Rect::new(a, b);
+    render(&polygon, &best, 2);
+    let area = best.area();
+
+    assert_eq!(area, 1516172795);
+    println!("Part 2: {area}");
 }
 
-impl Line {
-    fn new(a: Point, b: Point) -> Self {
-        Self { a, b }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_data() -> Vec<Point> {
+        TEST_DATA.trim().split("\n").map(Point::from).collect()
     }
 
-    fn bbox(&self) -> (i64, i64, i64, i64) {
-        let (min_lx, max_lx) = (self.a.x.min(self.b.x), self.a.x.max(self.b.x));
-        let (min_ly, max_ly) = (self.a.y.min(self.b.y), self.a.y.max(self.b.y));
-        (min_lx, max_lx, min_ly, max_ly)
+    fn p(x: i64, y: i64) -> Point {
+        Point { x, y }
     }
-}
 
-pub fn part_2() {
-    let data = get_data();
+    fn areas(rects: &[(Point, Point)]) -> Vec<i64> {
+        rects
+            .iter()
+            .map(|(a, b)| Rect::new(*a, *b).area())
+            .collect()
+    }
 
-    // Create lines
-    let mut lines: Vec<Line> = data.windows(2).map(|w| Line::new(w[0], w[1])).collect();
-    lines.push(Line::new(*data.last().unwrap(), *data.first().unwrap()));
+    #[test]
+    fn top_3_of_part_1() {
+        let data = test_data();
+        let top = top_rectangles(&data, 3, &Constraints::default(), |_| true);
+        // equal areas keep the input order
+        assert_eq!(
+            top,
+            vec![(p(11, 1), p(2, 5)), (p(11, 7), p(2, 3)), (p(9, 7), p(2, 3))]
+        );
+        assert_eq!(areas(&top), vec![50, 50, 40]);
+    }
 
-    // Check for every rectangle, whether any line intersects with it
-    // Calculate the area as above and select the largest
-    let area = data
-        .iter()
-        .tuple_combinations()
-        .filter(|(a, b)| {
-            let (min_x, max_x) = (a.x.min(b.x), a.x.max(b.x));
-            let (min_y, max_y) = (a.y.min(b.y), a.y.max(b.y));
-            !lines.iter().any(|l| {
-                let (min_lx, max_lx, min_ly, max_ly) = l.bbox();
-                // check for intersection
-                min_lx < max_x && max_lx > min_x && min_ly < max_y && max_ly > min_y
-            })
-        })
-        .map(|(a, b)| a.area(b))
-        .max()
-        .unwrap();
+    #[test]
+    fn top_3_of_part_2() {
+        let data = test_data();
+        let polygon = Polygon::new(data.clone());
+        let compressed = CompressedPolygon::new(&polygon);
+        let top = top_rectangles(&data, 3, &Constraints::default(), |r| {
+            compressed.contains_rect(r)
+        });
+        assert_eq!(
+            top,
+            vec![(p(9, 5), p(2, 3)), (p(11, 1), p(9, 7)), (p(2, 5), p(7, 3))]
+        );
+        assert_eq!(areas(&top), vec![24, 21, 18]);
+    }
 
-    assert_eq!(area, 1516172795);
-    println!("Part 2: {area}");
+    #[test]
+    fn part_2_containing_a_point() {
+        let data = test_data();
+        let polygon = Polygon::new(data.clone());
+        let compressed = CompressedPolygon::new(&polygon);
+        let constraints = Constraints {
+            contains: Some(p(10, 2)),
+            ..Default::default()
+        };
+        let top = top_rectangles(&data, 3, &constraints, |r| compressed.contains_rect(r));
+        assert_eq!(
+            top,
+            vec![
+                (p(11, 1), p(9, 7)),
+                (p(11, 1), p(9, 5)),
+                (p(11, 1), p(7, 3))
+            ]
+        );
+        assert_eq!(areas(&top), vec![21, 15, 15]);
+    }
+
+    #[test]
+    fn part_2_with_min_width() {
+        let data = test_data();
+        let polygon = Polygon::new(data.clone());
+        let compressed = CompressedPolygon::new(&polygon);
+        let constraints = Constraints {
+            min_width: 6,
+            ..Default::default()
+        };
+        let top = top_rectangles(&data, 3, &constraints, |r| compressed.contains_rect(r));
+        assert_eq!(
+            top,
+            vec![(p(9, 5), p(2, 3)), (p(2, 5), p(7, 3)), (p(9, 5), p(2, 5))]
+        );
+    }
+
+    #[test]
+    fn aspect_ratio_bounds() {
+        let data = test_data();
+        let constraints = Constraints {
+            min_aspect: Some(1.0),
+            max_aspect: Some(1.5),
+            ..Default::default()
+        };
+        let top = top_rectangles(&data, 3, &constraints, |_| true);
+        assert_eq!(
+            top,
+            vec![(p(7, 1), p(2, 5)), (p(11, 7), p(7, 3)), (p(11, 7), p(9, 5))]
+        );
+
+        // only two rectangles of this shape fit into the polygon
+        let polygon = Polygon::new(data.clone());
+        let compressed = CompressedPolygon::new(&polygon);
+        let top = top_rectangles(&data, 3, &constraints, |r| compressed.contains_rect(r));
+        assert_eq!(areas(&top), vec![9, 9]);
+    }
 }
@@ -0,0 +1,108 @@
+// Disjoint components over the elements 0..n
+// using union by size and path compression
+#[derive(Debug, Clone)]
+pub struct DisjointSet {
+    parent: Vec<usize>,
+    // number of elements in the component, only valid for the roots
+    size: Vec<usize>,
+    components: usize,
+}
+
+impl DisjointSet {
+    // Every element starts in its own component
+    pub fn new(n: usize) -> Self {
+        Self {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+            components: n,
+        }
+    }
+
+    // Find the root of the component containing x
+    // All visited elements are linked directly to the root afterwards
+    pub fn find(&mut self, x: usize) -> usize {
+        let mut root = x;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut x = x;
+        while self.parent[x] != root {
+            x = std::mem::replace(&mut self.parent[x], root);
+        }
+        root
+    }
+
+    // Join the components of a and b
+    // Returns false, when both already are in the same component
+    pub fn union(&mut self, a: usize, b: usize) -> bool {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        // attach the smaller tree below the larger one
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+        self.components -= 1;
+        true
+    }
+
+    // Size of the component containing x
+    // day8 reads the sizes from the groups, so this is only used by the tests
+    #[allow(dead_code)]
+    pub fn size(&mut self, x: usize) -> usize {
+        let root = self.find(x);
+        self.size[root]
+    }
+
+    // Number of components
+    pub fn components(&self) -> usize {
+        self.components
+    }
+
+    // The members of every component, ordered by their smallest element
+    pub fn groups(&mut self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<Vec<usize>> = Vec::with_capacity(self.components);
+        let mut group_of_root = vec![usize::MAX; self.parent.len()];
+        for x in 0..self.parent.len() {
+            let root = self.find(x);
+            if group_of_root[root] == usize::MAX {
+                group_of_root[root] = groups.len();
+                groups.push(Vec::new());
+            }
+            groups[group_of_root[root]].push(x);
+        }
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn union_and_find() {
+        let mut set = DisjointSet::new(6);
+        assert!(set.union(0, 1));
+        assert!(set.union(2, 3));
+        assert!(set.union(1, 3));
+        assert!(!set.union(0, 2));
+        assert_eq!(set.find(0), set.find(3));
+        assert_ne!(set.find(0), set.find(4));
+        assert_eq!(set.components(), 3);
+    }
+
+    #[test]
+    fn sizes_and_groups() {
+        let mut set = DisjointSet::new(6);
+        set.union(5, 1);
+        set.union(1, 3);
+        set.union(0, 4);
+        assert_eq!(set.size(3), 3);
+        assert_eq!(set.size(4), 2);
+        assert_eq!(set.size(2), 1);
+        assert_eq!(set.groups(), vec![vec![0, 4], vec![1, 3, 5], vec![2]]);
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, BinaryHeap},
+    ops::RangeInclusive,
+};
+
+// Ranges are kept sorted by start and never overlap or touch,
+// so two neighbouring ranges always have a gap of at least one value
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntervalSet {
+    ranges: Vec<RangeInclusive<i64>>,
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Insert a range, merging it with all overlapping or adjacent ranges
+    // Empty ranges are ignored
+    pub fn insert(&mut self, range: RangeInclusive<i64>) {
+        let (start, end) = (*range.start(), *range.end());
+        if start > end {
+            return;
+        }
+        // first range that ends at or after the value before start
+        let i = self
+            .ranges
+            .partition_point(|r| *r.end() < start.saturating_sub(1));
+        // first range that starts after the value behind end
+        let j = self
+            .ranges
+            .partition_point(|r| *r.start() <= end.saturating_add(1));
+        // all ranges in i..j overlap or touch the new range
+        let merged = if i < j {
+            start.min(*self.ranges[i].start())..=end.max(*self.ranges[j - 1].end())
+        } else {
+            range
+        };
+        self.ranges.splice(i..j, [merged]);
+    }
+
+    // Look up many values at once
+    // The values are sorted and swept against the ranges in a single pass
+    // Returns the index of the matching range for every value in input order
+    pub fn find_all(&self, values: &[i64]) -> Vec<Option<usize>> {
+        let mut order = (0..values.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|&i| values[i]);
+
+        let mut found = vec![None; values.len()];
+        let mut r = 0;
+        for i in order {
+            // skip all ranges ending before the value
+            // they can't match any of the following values either
+            while r < self.ranges.len() && *self.ranges[r].end() < values[i] {
+                r += 1;
+            }
+            match self.ranges.get(r) {
+                Some(range) if *range.start() <= values[i] => found[i] = Some(r),
+                _ => (),
+            }
+        }
+        found
+    }
+
+    // Number of values in the set
+    pub fn total_len(&self) -> i64 {
+        self.ranges.iter().map(|r| r.end() - r.start() + 1).sum()
+    }
+}
+
+// Look up many values in the unmerged input ranges
+// Merging forgets where a value came from, so the ranges are swept by start instead,
+// keeping all ranges that started at or before the value and end at or after it
+// Returns the lowest index of an input range containing the value, in input order
+pub fn find_sources(ranges: &[RangeInclusive<i64>], values: &[i64]) -> Vec<Option<usize>> {
+    let mut by_start = (0..ranges.len()).collect::<Vec<_>>();
+    by_start.sort_unstable_by_key(|&r| *ranges[r].start());
+    let mut order = (0..values.len()).collect::<Vec<_>>();
+    order.sort_unstable_by_key(|&i| values[i]);
+
+    let mut active = BTreeSet::new();
+    let mut ends = BinaryHeap::new();
+    let mut next = 0;
+    let mut found = vec![None; values.len()];
+    for i in order {
+        while next < by_start.len() && *ranges[by_start[next]].start() <= values[i] {
+            active.insert(by_start[next]);
+            ends.push(Reverse((*ranges[by_start[next]].end(), by_start[next])));
+            next += 1;
+        }
+        // ranges ending before the value are done for all following values too
+        while let Some(Reverse((end, r))) = ends.peek() {
+            if *end >= values[i] {
+                break;
+            }
+            active.remove(r);
+            ends.pop();
+        }
+        found[i] = active.first().copied();
+    }
+    found
+}
+
+// Single lookups and set algebra, day5 only needs the batch lookup and the total length
+#[allow(dead_code)]
+impl IntervalSet {
+    // Binary search for the range containing the value
+    pub fn find(&self, value: i64) -> Option<usize> {
+        let i = self.ranges.partition_point(|r| *r.end() < value);
+        match self.ranges.get(i) {
+            Some(r) if *r.start() <= value => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        self.find(value).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn ranges(&self) -> &[RangeInclusive<i64>] {
+        &self.ranges
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let mut set = self.clone();
+        set.extend(other.ranges.iter().cloned());
+        set
+    }
+
+    // Walk both sorted lists at once and keep the overlapping parts
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a, b) = (&self.ranges[i], &other.ranges[j]);
+            let start = *a.start().max(b.start());
+            let end = *a.end().min(b.end());
+            if start <= end {
+                ranges.push(start..=end);
+            }
+            // advance the range that ends first
+            if a.end() < b.end() {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        Self { ranges }
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        self.intersection(&other.complement(i64::MIN..=i64::MAX))
+    }
+
+    // All values within bounds, which are not in the set
+    pub fn complement(&self, bounds: RangeInclusive<i64>) -> Self {
+        let mut ranges = Vec::new();
+        let mut next = Some(*bounds.start());
+        for r in &self.ranges {
+            let Some(start) = next else {
+                break;
+            };
+            if *r.start() > start {
+                ranges.push(start..=(r.start() - 1).min(*bounds.end()));
+            }
+            next = r.end().checked_add(1).map(|n| n.max(start));
+        }
+        if let Some(start) = next {
+            ranges.push(start..=*bounds.end());
+        }
+        // the loop does not stop at the upper bound, so clean up afterwards
+        ranges.retain(|r| !r.is_empty() && r.start() <= bounds.end());
+        Self { ranges }
+    }
+}
+
+impl Extend<RangeInclusive<i64>> for IntervalSet {
+    fn extend<T: IntoIterator<Item = RangeInclusive<i64>>>(&mut self, iter: T) {
+        iter.into_iter().for_each(|r| self.insert(r));
+    }
+}
+
+impl FromIterator<RangeInclusive<i64>> for IntervalSet {
+    fn from_iter<T: IntoIterator<Item = RangeInclusive<i64>>>(iter: T) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ranges: &[RangeInclusive<i64>]) -> IntervalSet {
+        ranges.iter().cloned().collect()
+    }
+
+    #[test]
+    fn insert_merges_overlapping_and_adjacent_ranges() {
+        let empty = RangeInclusive::new(30, 29);
+        let s = set(&[10..=14, 3..=5, 16..=20, 12..=18, 6..=6, empty]);
+        assert_eq!(s.ranges(), &[3..=6, 10..=20]);
+        assert_eq!(s.total_len(), 15);
+    }
+
+    #[test]
+    fn lookups() {
+        let s = set(&[3..=5, 10..=20]);
+        assert_eq!(s.find(4), Some(0));
+        assert_eq!(s.find(20), Some(1));
+        assert_eq!(s.find(8), None);
+        assert!(!s.contains(21));
+        assert_eq!(
+            s.find_all(&[32, 1, 5, 11, 8, 17]),
+            vec![None, None, Some(0), Some(1), None, Some(1)]
+        );
+    }
+
+    #[test]
+    fn sources_are_the_unmerged_ranges() {
+        let ranges = [3..=5, 10..=14, 16..=20, 12..=18];
+        assert_eq!(
+            find_sources(&ranges, &[1, 5, 8, 11, 17, 32, 15, 13]),
+            vec![
+                None,
+                Some(0),
+                None,
+                Some(1),
+                Some(2),
+                None,
+                Some(3),
+                Some(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn set_algebra() {
+        let a = set(&[0..=10, 20..=30]);
+        let b = set(&[5..=24, 40..=41]);
+        assert_eq!(a.union(&b).ranges(), &[0..=30, 40..=41]);
+        assert_eq!(a.intersection(&b).ranges(), &[5..=10, 20..=24]);
+        assert_eq!(a.difference(&b).ranges(), &[0..=4, 25..=30]);
+        assert_eq!(b.difference(&a).ranges(), &[11..=19, 40..=41]);
+        assert!(a.intersection(&set(&[11..=19])).is_empty());
+    }
+
+    #[test]
+    fn complement_within_bounds() {
+        let a = set(&[0..=10, 20..=30]);
+        assert_eq!(a.complement(-5..=25).ranges(), &[-5..=-1, 11..=19]);
+        assert_eq!(a.complement(12..=15).ranges(), &[12..=15]);
+        assert!(a.complement(2..=8).is_empty());
+        assert_eq!(
+            set(&[i64::MIN..=0])
+                .complement(i64::MIN..=i64::MAX)
+                .ranges(),
+            &[1..=i64::MAX]
+        );
+    }
+}
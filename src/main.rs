@@ -11,6 +11,10 @@ mod day7;
 mod day8;
 mod day9;
 
+mod dag;
+mod disjoint_set;
+mod interval_set;
+mod polygon;
 mod utils;
 
 use dotenvy::dotenv;
@@ -0,0 +1,501 @@
+// Axis aligned polygons on the tile grid.
+// The polygon runs through the centres of its vertex tiles,
+// its border belongs to the polygon.
+
+use std::{collections::HashMap, fmt::Display};
+
+use itertools::Itertools;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Point {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl From<&str> for Point {
+    fn from(value: &str) -> Self {
+        let mut s = value.trim().split(',').map(|s| s.parse().unwrap());
+        Self {
+            x: s.next().unwrap(),
+            y: s.next().unwrap(),
+        }
+    }
+}
+
+// Rectangle spanned by two opposite corners including its border
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Rect {
+    pub fn new(a: Point, b: Point) -> Self {
+        Self {
+            min: Point {
+                x: a.x.min(b.x),
+                y: a.y.min(b.y),
+            },
+            max: Point {
+                x: a.x.max(b.x),
+                y: a.y.max(b.y),
+            },
+        }
+    }
+
+    // Number of tiles covered in x direction
+    pub fn width(&self) -> i64 {
+        self.max.x - self.min.x + 1
+    }
+
+    // Number of tiles covered in y direction
+    pub fn height(&self) -> i64 {
+        self.max.y - self.min.y + 1
+    }
+
+    // Number of tiles covered by the rectangle
+    pub fn area(&self) -> i64 {
+        self.width() * self.height()
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        (self.min.x..=self.max.x).contains(&p.x) && (self.min.y..=self.max.y).contains(&p.y)
+    }
+}
+
+// Direction of the vertices as seen on screen with y pointing down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+}
+
+impl Display for Winding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Clockwise => write!(f, "clockwise"),
+            Self::CounterClockwise => write!(f, "counter clockwise"),
+        }
+    }
+}
+
+// Reasons for a vertex list not forming a simple, closed, axis aligned loop
+// Vertices and edges are named by their index, edge i runs from vertex i to i + 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolygonError {
+    TooFewVertices(usize),
+    DuplicateVertex(usize, usize, Point),
+    NotAxisAligned(usize, usize, Point, Point),
+    SelfIntersection(usize, usize),
+}
+
+impl Display for PolygonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooFewVertices(n) => write!(f, "only {n} vertices, at least 4 are needed"),
+            Self::DuplicateVertex(i, j, p) => {
+                write!(f, "vertices {i} and {j} are both at ({},{})", p.x, p.y)
+            }
+            Self::NotAxisAligned(i, j, a, b) => write!(
+                f,
+                "vertices {i} ({},{}) and {j} ({},{}) are not on a common row or column",
+                a.x, a.y, b.x, b.y
+            ),
+            Self::SelfIntersection(i, j) => write!(f, "edges {i} and {j} intersect"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    vertices: Vec<Point>,
+}
+
+impl Polygon {
+    // The vertices in order, the last one connects back to the first
+    pub fn new(vertices: Vec<Point>) -> Self {
+        Self { vertices }
+    }
+
+    pub fn vertices(&self) -> &[Point] {
+        &self.vertices
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = (Point, Point)> + '_ {
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(a, b)| (*a, *b))
+    }
+
+    // Check that the vertices form a simple, closed and axis aligned loop
+    // Returns the winding direction of a valid polygon
+    pub fn validate(&self) -> Result<Winding, PolygonError> {
+        let n = self.vertices.len();
+        if n < 4 {
+            return Err(PolygonError::TooFewVertices(n));
+        }
+
+        let mut seen = HashMap::new();
+        for (i, v) in self.vertices.iter().enumerate() {
+            if let Some(j) = seen.insert(*v, i) {
+                return Err(PolygonError::DuplicateVertex(j, i, *v));
+            }
+        }
+
+        for (i, (a, b)) in self.edges().enumerate() {
+            if a.x != b.x && a.y != b.y {
+                return Err(PolygonError::NotAxisAligned(i, (i + 1) % n, a, b));
+            }
+        }
+
+        // Axis aligned edges touch, when their bounding boxes do
+        // Neighbouring edges always share a vertex,
+        // they only intersect when the second one runs back along the first one
+        let edges = self.edges().map(|(a, b)| Rect::new(a, b)).collect_vec();
+        for (i, j) in (0..n).tuple_combinations() {
+            let (e, f) = (&edges[i], &edges[j]);
+            let touching = e.min.x <= f.max.x
+                && e.max.x >= f.min.x
+                && e.min.y <= f.max.y
+                && e.max.y >= f.min.y;
+            if !touching {
+                continue;
+            }
+            let neighbours = j == i + 1 || (i == 0 && j == n - 1);
+            let collinear = (e.min.x == e.max.x && f.min.x == f.max.x && e.min.x == f.min.x)
+                || (e.min.y == e.max.y && f.min.y == f.max.y && e.min.y == f.min.y);
+            if !neighbours || (collinear && self.turns_back(i, j)) {
+                return Err(PolygonError::SelfIntersection(i, j));
+            }
+        }
+
+        Ok(self.winding())
+    }
+
+    // Check whether two neighbouring collinear edges point into opposite directions
+    fn turns_back(&self, i: usize, j: usize) -> bool {
+        let (a, b) = self.edges().nth(i).unwrap();
+        let (c, d) = self.edges().nth(j).unwrap();
+        (b.x - a.x).signum() != (d.x - c.x).signum() || (b.y - a.y).signum() != (d.y - c.y).signum()
+    }
+
+    // The sign of the shoelace formula gives the direction
+    pub fn winding(&self) -> Winding {
+        let area: i128 = self
+            .edges()
+            .map(|(a, b)| a.x as i128 * b.y as i128 - b.x as i128 * a.y as i128)
+            .sum();
+        if area > 0 {
+            Winding::Clockwise
+        } else {
+            Winding::CounterClockwise
+        }
+    }
+
+    // Check whether the point lies inside of the polygon or on its border
+    pub fn contains(&self, p: Point) -> bool {
+        self.contains_doubled(2 * p.x, 2 * p.y)
+    }
+
+    // Same as contains, but with doubled coordinates
+    // so points halfway between two tiles can be checked, too
+    fn contains_doubled(&self, x: i64, y: i64) -> bool {
+        let mut inside = false;
+        for (a, b) in self.edges() {
+            let r = Rect::new(a, b);
+            let (min_x, max_x, min_y, max_y) = (2 * r.min.x, 2 * r.max.x, 2 * r.min.y, 2 * r.max.y);
+            if (min_x..=max_x).contains(&x) && (min_y..=max_y).contains(&y) {
+                // on the border
+                return true;
+            }
+            // cast a ray to the right and count the crossed vertical edges
+            // The half open range makes a ray through a vertex count only once
+            if a.x == b.x && min_x > x && (min_y..max_y).contains(&y) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    // Check whether the rectangle lies completely inside of the polygon
+    pub fn contains_rect(&self, rect: &Rect) -> bool {
+        let (min, max) = (rect.min, rect.max);
+        if min.x < max.x && min.y < max.y {
+            // No edge may pass through the inside of the rectangle,
+            // because there would be outside points right next to it
+            let crossed = self.edges().any(|(a, b)| {
+                let e = Rect::new(a, b);
+                e.min.x < max.x && e.max.x > min.x && e.min.y < max.y && e.max.y > min.y
+            });
+            // Without a crossing edge the inside of the rectangle
+            // is either completely inside or outside of the polygon,
+            // so checking the centre is enough
+            return !crossed && self.contains_doubled(min.x + max.x, min.y + max.y);
+        }
+
+        // The rectangle is just a line or a single point
+        // Inside and outside only change at the coordinates of the vertices,
+        // so check the vertex coordinates along the line and the points between them
+        let mut steps = self
+            .vertices
+            .iter()
+            .flat_map(|v| [Point { x: v.x, y: min.y }, Point { x: min.x, y: v.y }])
+            .chain([min, max])
+            .filter(|p| (min.x..=max.x).contains(&p.x) && (min.y..=max.y).contains(&p.y))
+            .map(|p| (p.x, p.y))
+            .collect::<Vec<_>>();
+        steps.sort_unstable();
+        steps.dedup();
+        steps
+            .iter()
+            .all(|(x, y)| self.contains(Point { x: *x, y: *y }))
+            && steps
+                .windows(2)
+                .all(|w| self.contains_doubled(w[0].0 + w[1].0, w[0].1 + w[1].1))
+    }
+}
+
+// Polygon rasterised on its compressed coordinates
+// The vertex coordinates split the plane into cells,
+// the open area of every cell is either completely inside or outside of the polygon.
+// The same holds for the pieces of the grid lines between two neighbouring coordinates.
+// Prefix sums over the outside cells and line pieces answer rectangle queries in constant time
+#[derive(Debug, Clone)]
+pub struct CompressedPolygon<'a> {
+    polygon: &'a Polygon,
+    xs: Vec<i64>,
+    ys: Vec<i64>,
+    // number of outside cells left and above of every cell corner
+    cells: Vec<u32>,
+    // number of outside pieces left of every corner on each horizontal line
+    rows: Vec<u32>,
+    // number of outside pieces above every corner on each vertical line
+    cols: Vec<u32>,
+}
+
+impl<'a> CompressedPolygon<'a> {
+    pub fn new(polygon: &'a Polygon) -> Self {
+        let xs = polygon
+            .vertices
+            .iter()
+            .map(|v| v.x)
+            .sorted_unstable()
+            .dedup()
+            .collect_vec();
+        let ys = polygon
+            .vertices
+            .iter()
+            .map(|v| v.y)
+            .sorted_unstable()
+            .dedup()
+            .collect_vec();
+        let (w, h) = (xs.len(), ys.len());
+        let index = |coords: &[i64], c: i64| coords.binary_search(&c).unwrap();
+
+        // Every vertical edge toggles inside and outside for the cells right of it
+        // Mark the toggles at the first and behind the last row crossed by the edge,
+        // then spread them down the columns and along the rows
+        let mut inside = vec![false; w * h];
+        for (a, b) in polygon.edges().filter(|(a, b)| a.x == b.x && a.y != b.y) {
+            let col = index(&xs, a.x);
+            inside[index(&ys, a.y.min(b.y)) * w + col] ^= true;
+            inside[index(&ys, a.y.max(b.y)) * w + col] ^= true;
+        }
+        for i in w..w * h {
+            inside[i] ^= inside[i - w];
+        }
+        for i in (0..w * h).filter(|i| i % w > 0) {
+            inside[i] ^= inside[i - 1];
+        }
+        // cells beyond the last coordinates are always outside
+        let outside =
+            |col: usize, row: usize| col >= w - 1 || row >= h - 1 || !inside[row * w + col];
+
+        let mut cells = vec![0; w * h];
+        for row in 1..h {
+            for col in 1..w {
+                cells[row * w + col] = cells[(row - 1) * w + col] + cells[row * w + col - 1]
+                    - cells[(row - 1) * w + col - 1]
+                    + outside(col - 1, row - 1) as u32;
+            }
+        }
+
+        // A line piece is inside, when one of the cells next to it is inside.
+        // The polygon border always has an inside cell on one side
+        // and all other pieces are surrounded by either inside or outside cells
+        let mut rows = vec![0; w * h];
+        for row in 0..h {
+            for col in 1..w {
+                let above = row == 0 || outside(col - 1, row - 1);
+                rows[row * w + col] =
+                    rows[row * w + col - 1] + (above && outside(col - 1, row)) as u32;
+            }
+        }
+        let mut cols = vec![0; w * h];
+        for col in 0..w {
+            for row in 1..h {
+                let left = col == 0 || outside(col - 1, row - 1);
+                cols[col * h + row] =
+                    cols[col * h + row - 1] + (left && outside(col, row - 1)) as u32;
+            }
+        }
+
+        Self {
+            polygon,
+            xs,
+            ys,
+            cells,
+            rows,
+            cols,
+        }
+    }
+
+    // Check whether the rectangle lies completely inside of the polygon
+    // Constant time for rectangles spanned by vertex coordinates,
+    // all others are checked against the polygon directly
+    pub fn contains_rect(&self, rect: &Rect) -> bool {
+        let (min, max) = (rect.min, rect.max);
+        let corners = (
+            self.xs.binary_search(&min.x),
+            self.xs.binary_search(&max.x),
+            self.ys.binary_search(&min.y),
+            self.ys.binary_search(&max.y),
+        );
+        let (Ok(x0), Ok(x1), Ok(y0), Ok(y1)) = corners else {
+            return self.polygon.contains_rect(rect);
+        };
+        let (w, h) = (self.xs.len(), self.ys.len());
+        let cell = |col: usize, row: usize| self.cells[row * w + col];
+        match (x0 < x1, y0 < y1) {
+            (true, true) => cell(x1, y1) + cell(x0, y0) == cell(x0, y1) + cell(x1, y0),
+            (true, false) => self.rows[y0 * w + x1] == self.rows[y0 * w + x0],
+            (false, true) => self.cols[x0 * h + y1] == self.cols[x0 * h + y0],
+            // A single point is inside, when any of the four cells around it is inside
+            (false, false) => {
+                let outside = |col: usize, row: usize| {
+                    cell(col + 1, row + 1) + cell(col, row)
+                        != cell(col, row + 1) + cell(col + 1, row)
+                };
+                let cols = x0.saturating_sub(1)..(x0 + 1).min(w - 1);
+                let rows = y0.saturating_sub(1)..(y0 + 1).min(h - 1);
+                !cols.cartesian_product(rows).all(|(c, r)| outside(c, r))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(vertices: &[(i64, i64)]) -> Polygon {
+        Polygon::new(
+            vertices
+                .iter()
+                .map(|(x, y)| Point { x: *x, y: *y })
+                .collect(),
+        )
+    }
+
+    // U shape with a notch open to the bottom between x = 2 and x = 4
+    fn notched() -> Polygon {
+        polygon(&[
+            (0, 0),
+            (6, 0),
+            (6, 6),
+            (4, 6),
+            (4, 2),
+            (2, 2),
+            (2, 6),
+            (0, 6),
+        ])
+    }
+
+    fn rect(a: (i64, i64), b: (i64, i64)) -> Rect {
+        Rect::new(Point { x: a.0, y: a.1 }, Point { x: b.0, y: b.1 })
+    }
+
+    // Check the rectangle with both implementations
+    fn contains_rect(polygon: &Polygon, r: Rect) -> bool {
+        let inside = polygon.contains_rect(&r);
+        assert_eq!(
+            CompressedPolygon::new(polygon).contains_rect(&r),
+            inside,
+            "{r:?}"
+        );
+        inside
+    }
+
+    #[test]
+    fn points() {
+        let p = notched();
+        assert!(p.contains(Point { x: 1, y: 1 }));
+        assert!(p.contains(Point { x: 5, y: 5 }));
+        assert!(p.contains(Point { x: 3, y: 2 }));
+        assert!(p.contains(Point { x: 0, y: 6 }));
+        assert!(!p.contains(Point { x: 3, y: 4 }));
+        assert!(!p.contains(Point { x: 3, y: 6 }));
+        assert!(!p.contains(Point { x: 7, y: 3 }));
+    }
+
+    #[test]
+    fn rectangles() {
+        let p = notched();
+        assert!(contains_rect(&p, rect((0, 0), (6, 2))));
+        assert!(contains_rect(&p, rect((0, 0), (2, 6))));
+        assert!(contains_rect(&p, rect((6, 6), (4, 0))));
+        assert!(contains_rect(&p, rect((1, 1), (5, 2))));
+        // fully outside
+        assert!(!contains_rect(&p, rect((8, 8), (10, 10))));
+        assert!(!contains_rect(&p, rect((-3, -3), (-1, 7))));
+        // spanning the notch
+        assert!(!contains_rect(&p, rect((1, 1), (5, 5))));
+        assert!(!contains_rect(&p, rect((0, 0), (6, 6))));
+        assert!(!contains_rect(&p, rect((3, 1), (5, 5))));
+    }
+
+    #[test]
+    fn lines_and_points() {
+        let p = notched();
+        assert!(contains_rect(&p, rect((0, 2), (6, 2))));
+        assert!(contains_rect(&p, rect((2, 2), (2, 6))));
+        assert!(contains_rect(&p, rect((3, 0), (3, 2))));
+        assert!(contains_rect(&p, rect((1, 1), (5, 1))));
+        assert!(!contains_rect(&p, rect((0, 4), (6, 4))));
+        assert!(!contains_rect(&p, rect((3, 0), (3, 3))));
+        assert!(!contains_rect(&p, rect((0, 6), (6, 6))));
+        assert!(contains_rect(&p, rect((3, 2), (3, 2))));
+        assert!(contains_rect(&p, rect((2, 6), (2, 6))));
+        assert!(!contains_rect(&p, rect((3, 3), (3, 3))));
+        assert!(!contains_rect(&p, rect((3, 6), (3, 6))));
+        assert!(!contains_rect(&p, rect((7, 7), (7, 7))));
+    }
+
+    // Every rectangle within and around the polygons, compared to checking every half tile
+    #[test]
+    fn brute_force() {
+        let staircase = polygon(&[
+            (0, 0),
+            (3, 0),
+            (3, 2),
+            (5, 2),
+            (5, 5),
+            (1, 5),
+            (1, 3),
+            (0, 3),
+        ]);
+        for p in [notched(), staircase] {
+            assert!(p.validate().is_ok());
+            // all pairs of coordinates with a <= b
+            let pairs = (-1..=7).flat_map(|a| (a..=7).map(move |b| (a, b)));
+            for ((x0, x1), (y0, y1)) in pairs.clone().cartesian_product(pairs) {
+                let r = rect((x0, y0), (x1, y1));
+                let expected = (2 * x0..=2 * x1)
+                    .cartesian_product(2 * y0..=2 * y1)
+                    .all(|(x, y)| p.contains_doubled(x, y));
+                assert_eq!(contains_rect(&p, r), expected, "{r:?}");
+            }
+        }
+    }
+}
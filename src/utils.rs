@@ -14,3 +14,15 @@ pub fn get_input(day: u32) -> reqwest::Result<String> {
         .send()?
         .text()
 }
+
+// Value following a command line option, e.g. `--render svg`
+pub fn get_arg(name: &str) -> Option<String> {
+    let mut args = env::args().skip(1);
+    args.find(|a| a == name)?;
+    args.next()
+}
+
+// Command line flag without a value, e.g. `--plan`
+pub fn has_arg(name: &str) -> bool {
+    env::args().skip(1).any(|a| a == name)
+}